@@ -0,0 +1,8 @@
+-- 账户
+CREATE TABLE IF NOT EXISTS account (
+    id SERIAL PRIMARY KEY,
+    username VARCHAR(50) NOT NULL,
+    balance INTEGER NOT NULL DEFAULT 0,
+    CONSTRAINT account_username_key UNIQUE (username),
+    CONSTRAINT account_balance_non_negative CHECK (balance >= 0)
+);
@@ -0,0 +1,12 @@
+-- 转账流水，只追加不修改；不加外键，账户删除后流水仍需保留用于审计
+CREATE TABLE IF NOT EXISTS transfers (
+    id BIGSERIAL PRIMARY KEY,
+    from_id INTEGER NOT NULL,
+    to_id INTEGER NOT NULL,
+    amount INTEGER NOT NULL CHECK (amount > 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'completed',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS transfers_from_id_idx ON transfers (from_id);
+CREATE INDEX IF NOT EXISTS transfers_to_id_idx ON transfers (to_id);
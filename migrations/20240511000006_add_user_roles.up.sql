@@ -0,0 +1,2 @@
+-- 用户的角色，新用户默认为普通用户
+ALTER TABLE users ADD COLUMN IF NOT EXISTS roles TEXT[] NOT NULL DEFAULT ARRAY['user'];
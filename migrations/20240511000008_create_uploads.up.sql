@@ -0,0 +1,16 @@
+-- 上传文件目录；内容相同的文件共用同一个存储对象（storage_key）
+CREATE TABLE IF NOT EXISTS uploads (
+    id SERIAL PRIMARY KEY,
+    -- 不级联删除：删除用户前需要先删除文件，否则存储对象无人清理
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    filename VARCHAR(255) NOT NULL,
+    storage_key VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL,
+    sha256 CHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS uploads_user_id_idx ON uploads (user_id, id);
+CREATE INDEX IF NOT EXISTS uploads_sha256_idx ON uploads (sha256);
+CREATE INDEX IF NOT EXISTS uploads_storage_key_idx ON uploads (storage_key);
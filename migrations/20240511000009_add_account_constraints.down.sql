@@ -0,0 +1,2 @@
+-- 约束可能由 000001 建表时创建，回滚 000001 时会随表一起删除，这里不做处理
+SELECT 1;
@@ -0,0 +1,19 @@
+-- 账户表可能在引入迁移之前已经手工建好，此时 000001 的 CREATE TABLE IF NOT EXISTS 不会补上约束，
+-- 这里单独检查并添加缺少的约束
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'account'::regclass AND conname = 'account_username_key'
+    ) THEN
+        ALTER TABLE account ADD CONSTRAINT account_username_key UNIQUE (username);
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'account'::regclass AND conname = 'account_balance_non_negative'
+    ) THEN
+        ALTER TABLE account ADD CONSTRAINT account_balance_non_negative CHECK (balance >= 0);
+    END IF;
+END
+$$;
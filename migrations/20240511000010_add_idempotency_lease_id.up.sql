@@ -0,0 +1,2 @@
+-- 占用 key 的请求的租约 ID，租约被其它请求接管后旧请求不能再写入或释放
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS lease_id CHAR(32);
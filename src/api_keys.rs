@@ -0,0 +1,361 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::authz::{self, Principal, RequireScope, Scope};
+use crate::error::AppError;
+use crate::users;
+
+/// 携带 API Key 的请求头
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// API Key 明文的前缀，便于识别和扫描泄露
+const KEY_PREFIX: &str = "ak_";
+/// 保存到数据库、用于展示的明文前几个字符
+const DISPLAY_PREFIX_LENGTH: usize = 11;
+
+/// 创建和吊销 API Key 的权限范围
+///
+/// 不会授予 API Key 本身，泄露的 Key 无法用来创建新的 Key 或吊销其他 Key。
+pub struct ApiKeysWrite;
+
+impl Scope for ApiKeysWrite {
+    const NAME: &'static str = "api-keys:write";
+}
+
+/// API Key，不包含明文和哈希
+#[derive(Serialize, Debug, FromRow)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// 创建 API Key
+#[derive(Deserialize)]
+pub struct CreateApiKey {
+    pub name: String,
+    /// 申请的权限范围（空格分隔），省略时授予当前身份拥有的全部范围；
+    /// 不包括 [`ApiKeysWrite`]
+    pub scope: Option<String>,
+}
+
+/// 通过 API Key 认证得到的用户和权限范围
+#[derive(FromRow)]
+struct KeyOwner {
+    username: String,
+    roles: Vec<String>,
+    scopes: Vec<String>,
+}
+
+/// API Key 管理路由，需要已认证的身份：`GET /`、`POST /`、`DELETE /:id`；
+/// 创建和吊销还需要 [`ApiKeysWrite`]
+pub fn router() -> Router<Pool<Postgres>> {
+    Router::new()
+        .route("/", get(list_handler).post(create_handler))
+        .route("/:id", delete(revoke_handler))
+}
+
+/// 为用户创建 API Key，返回记录和明文；明文不会保存，只能在这里取得
+pub async fn create(
+    db: &Pool<Postgres>,
+    user_id: i32,
+    name: &str,
+    scopes: &[String],
+) -> Result<(ApiKey, String), AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(AppError::BadRequest("名称为 1-64 个字符".to_string()));
+    }
+
+    let key = format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    let api_key = sqlx::query_as::<_, ApiKey>(
+        "INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes) VALUES ($1, $2, $3, $4, $5) \
+         RETURNING id, name, prefix, scopes, created_at, last_used_at, revoked_at",
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(&key[..DISPLAY_PREFIX_LENGTH])
+    .bind(hash_key(&key))
+    .bind(scopes)
+    .fetch_one(db)
+    .await?;
+    Ok((api_key, key))
+}
+
+/// 校验 API Key，无效或已吊销时返回 `None`，同时记录最近使用时间
+///
+/// 身份的角色取用户当前的角色，权限范围取创建 Key 时授予的范围。
+pub async fn authenticate(db: &Pool<Postgres>, key: &str) -> Result<Option<Principal>, AppError> {
+    if !key.starts_with(KEY_PREFIX) {
+        return Ok(None);
+    }
+    let owner = sqlx::query_as::<_, KeyOwner>(
+        "UPDATE api_keys k SET last_used_at = now() FROM users u \
+         WHERE k.key_hash = $1 AND k.revoked_at IS NULL AND u.id = k.user_id \
+         RETURNING u.username, u.roles, k.scopes",
+    )
+    .bind(hash_key(key))
+    .fetch_optional(db)
+    .await?;
+    // 即使数据库中的记录包含管理 API Key 的范围，也不授予
+    Ok(owner.map(|owner| Principal {
+        subject: owner.username,
+        roles: owner.roles,
+        scopes: Some(
+            owner
+                .scopes
+                .into_iter()
+                .filter(|scope| scope != ApiKeysWrite::NAME)
+                .collect(),
+        ),
+    }))
+}
+
+/// 用户的全部 API Key，包括已吊销的
+pub async fn list(db: &Pool<Postgres>, user_id: i32) -> Result<Vec<ApiKey>, AppError> {
+    let keys = sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, prefix, scopes, created_at, last_used_at, revoked_at FROM api_keys \
+         WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    Ok(keys)
+}
+
+/// 吊销用户的 API Key，不存在或已吊销时返回 404
+pub async fn revoke(db: &Pool<Postgres>, user_id: i32, id: i32) -> Result<(), AppError> {
+    let result = sqlx::query(
+        "UPDATE api_keys SET revoked_at = now() \
+         WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("API Key 不存在".to_string()));
+    }
+    Ok(())
+}
+
+async fn create_handler(
+    State(db): State<Pool<Postgres>>,
+    RequireScope { principal, .. }: RequireScope<ApiKeysWrite>,
+    Json(body): Json<CreateApiKey>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user_id, roles) = owner(&db, &principal).await?;
+    let scopes = key_scopes(&principal, &roles, body.scope.as_deref());
+    let (api_key, key) = create(&db, user_id, &body.name, &scopes).await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LOCATION,
+        format!("/api-keys/{}", api_key.id).parse().unwrap(),
+    );
+    Ok((
+        StatusCode::CREATED,
+        headers,
+        Json(json!({"status": "success", "data": api_key, "key": key})),
+    ))
+}
+
+async fn list_handler(
+    State(db): State<Pool<Postgres>>,
+    principal: Principal,
+) -> Result<impl IntoResponse, AppError> {
+    let (user_id, _) = owner(&db, &principal).await?;
+    let keys = list(&db, user_id).await?;
+    Ok(Json(json!({"status": "success", "data": keys})))
+}
+
+async fn revoke_handler(
+    State(db): State<Pool<Postgres>>,
+    RequireScope { principal, .. }: RequireScope<ApiKeysWrite>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user_id, _) = owner(&db, &principal).await?;
+    revoke(&db, user_id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// 当前身份对应的用户 id 和角色
+async fn owner(db: &Pool<Postgres>, principal: &Principal) -> Result<(i32, Vec<String>), AppError> {
+    users::find_by_subject(db, &principal.subject)
+        .await?
+        .map(|user| (user.id, user.roles))
+        .ok_or_else(|| AppError::Unauthorized("用户不存在".to_string()))
+}
+
+// 新 Key 的权限范围：不能超过当前身份拥有的范围，也不能再管理 API Key
+fn key_scopes(principal: &Principal, roles: &[String], requested: Option<&str>) -> Vec<String> {
+    authz::grant_scopes(roles, requested)
+        .into_iter()
+        .filter(|scope| scope != ApiKeysWrite::NAME && principal.has_scope(scope))
+        .collect()
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    async fn insert_user(db: &Pool<Postgres>, username: &str) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, 'x') RETURNING id",
+        )
+        .bind(username)
+        .bind(format!("{username}@example.com"))
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn only_the_hash_is_stored(db: Pool<Postgres>) {
+        let user_id = insert_user(&db, "alice").await;
+        let (api_key, key) = create(&db, user_id, "ci", &strings(&["files:read"]))
+            .await
+            .unwrap();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(api_key.prefix, key[..DISPLAY_PREFIX_LENGTH]);
+
+        let (key_hash, row): (String, String) =
+            sqlx::query_as("SELECT key_hash, api_keys::text FROM api_keys WHERE id = $1")
+                .bind(api_key.id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(key_hash, hex::encode(Sha256::digest(key.as_bytes())));
+        assert!(!row.contains(&key));
+    }
+
+    #[sqlx::test]
+    async fn authenticates_with_granted_scopes(db: Pool<Postgres>) {
+        let user_id = insert_user(&db, "alice").await;
+        let (api_key, key) = create(&db, user_id, "ci", &strings(&["files:read"]))
+            .await
+            .unwrap();
+        assert!(api_key.last_used_at.is_none());
+
+        let principal = authenticate(&db, &key).await.unwrap().unwrap();
+        assert_eq!(principal.subject, "alice");
+        assert_eq!(principal.roles, strings(&["user"]));
+        assert!(principal.has_scope("files:read"));
+        assert!(!principal.has_scope("files:write"));
+
+        let keys = list(&db, user_id).await.unwrap();
+        assert!(keys[0].last_used_at.is_some());
+    }
+
+    #[sqlx::test]
+    async fn rejects_unknown_and_revoked_keys(db: Pool<Postgres>) {
+        let user_id = insert_user(&db, "alice").await;
+        let (api_key, key) = create(&db, user_id, "ci", &[]).await.unwrap();
+
+        // 前缀不对的直接拒绝，格式正确但不存在的查不到
+        assert!(authenticate(&db, "not-a-key").await.unwrap().is_none());
+        let unknown = format!("{}{}", KEY_PREFIX, "0".repeat(64));
+        assert!(authenticate(&db, &unknown).await.unwrap().is_none());
+        assert!(authenticate(&db, &key[..key.len() - 1])
+            .await
+            .unwrap()
+            .is_none());
+
+        revoke(&db, user_id, api_key.id).await.unwrap();
+        assert!(authenticate(&db, &key).await.unwrap().is_none());
+        // 重复吊销返回 404
+        assert!(matches!(
+            revoke(&db, user_id, api_key.id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn cannot_revoke_keys_of_other_users(db: Pool<Postgres>) {
+        let alice = insert_user(&db, "alice").await;
+        let bob = insert_user(&db, "bob").await;
+        let (api_key, key) = create(&db, alice, "ci", &[]).await.unwrap();
+
+        assert!(matches!(
+            revoke(&db, bob, api_key.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(authenticate(&db, &key).await.unwrap().is_some());
+    }
+
+    #[sqlx::test]
+    async fn key_never_carries_key_management_scope(db: Pool<Postgres>) {
+        let user_id = insert_user(&db, "alice").await;
+        // 绕过 create_handler 直接写入
+        let (_, key) = create(
+            &db,
+            user_id,
+            "ci",
+            &strings(&["files:read", ApiKeysWrite::NAME]),
+        )
+        .await
+        .unwrap();
+
+        let principal = authenticate(&db, &key).await.unwrap().unwrap();
+        assert!(principal.has_scope("files:read"));
+        assert!(!principal.has_scope(ApiKeysWrite::NAME));
+    }
+
+    #[test]
+    fn new_key_scopes_exclude_key_management() {
+        let roles = strings(&["user"]);
+        let browser = Principal {
+            subject: "alice".to_string(),
+            roles: roles.clone(),
+            scopes: None,
+        };
+        assert_eq!(
+            key_scopes(&browser, &roles, None),
+            strings(&["profile:read", "profile:write", "files:read", "files:write"])
+        );
+        assert_eq!(
+            key_scopes(&browser, &roles, Some("files:read api-keys:write")),
+            strings(&["files:read"])
+        );
+    }
+
+    #[test]
+    fn new_key_scopes_do_not_exceed_the_caller() {
+        let roles = strings(&["user"]);
+        let token = Principal {
+            subject: "alice".to_string(),
+            roles: roles.clone(),
+            scopes: Some(strings(&["files:read", ApiKeysWrite::NAME])),
+        };
+        assert_eq!(key_scopes(&token, &roles, None), strings(&["files:read"]));
+        assert!(key_scopes(&token, &roles, Some("files:write")).is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sqlx::{Pool, Postgres};
+
+use crate::api_keys::{self, API_KEY_HEADER};
+use crate::authz::{AuthzError, Principal, SESSION_KEY};
+use crate::error::AppError;
+use crate::jwt::{self, AccessClaims, Claims, TokenStore};
+use crate::session::Session;
+
+/// 认证中间件的状态
+#[derive(Clone)]
+pub struct Authenticator {
+    db: Pool<Postgres>,
+    tokens: TokenStore,
+}
+
+impl Authenticator {
+    pub fn new(db: Pool<Postgres>, tokens: TokenStore) -> Self {
+        Self { db, tokens }
+    }
+
+    /// 按 API Key、`Authorization: Bearer` 访问令牌、Session 的顺序解析身份
+    ///
+    /// 请求携带了凭据但凭据无效时直接失败，不会再尝试后面的方式。
+    pub async fn resolve(
+        &self,
+        headers: &HeaderMap,
+        session: Option<&Session>,
+    ) -> Result<Principal, Response> {
+        if let Some(value) = headers.get(API_KEY_HEADER) {
+            let key = value.to_str().unwrap_or_default();
+            return match api_keys::authenticate(&self.db, key).await {
+                Ok(Some(principal)) => Ok(principal),
+                Ok(None) => Err(AppError::Unauthorized("API Key 无效".to_string()).into_response()),
+                Err(err) => Err(err.into_response()),
+            };
+        }
+        if headers.contains_key(header::AUTHORIZATION) {
+            let token = jwt::bearer_token(headers).map_err(IntoResponse::into_response)?;
+            let claims: Claims<AccessClaims> = self
+                .tokens
+                .verify(token)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(Principal::from(claims));
+        }
+        if let Some(session) = session {
+            match session.get::<Principal>(SESSION_KEY).await {
+                Ok(Some(principal)) => return Ok(principal),
+                Ok(None) => {}
+                Err(err) => return Err(AuthzError::from(err).into_response()),
+            }
+        }
+        Err(AuthzError::Unauthenticated.into_response())
+    }
+}
+
+/// 认证中间件：解析身份并放入请求扩展，无法认证时返回 401
+///
+/// 用 `route_layer` 加在需要认证的路由上：
+/// `get(handler).route_layer(middleware::from_fn_with_state(authenticator, auth::require_auth))`
+pub async fn require_auth(
+    State(authenticator): State<Authenticator>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // 已经被外层中间件认证过
+    if request.extensions().get::<Principal>().is_some() {
+        return next.run(request).await;
+    }
+    let session = request.extensions().get::<Session>().cloned();
+    match authenticator
+        .resolve(request.headers(), session.as_ref())
+        .await
+    {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::StatusCode, routing::get, Extension, Router};
+    use chrono::Utc;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::jwt::Keys;
+    use crate::redis_client::RedisPool;
+    use crate::session::MemorySessionStore;
+
+    // 以下测试都不会走到需要 Redis 的一步
+    fn authenticator(db: Pool<Postgres>) -> Authenticator {
+        let tokens = TokenStore::new(RedisPool::disconnected(), &AppConfig::global().jwt);
+        Authenticator::new(db, tokens)
+    }
+
+    async fn api_key(db: &Pool<Postgres>, username: &str) -> String {
+        let user_id: i32 = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, 'x') RETURNING id",
+        )
+        .bind(username)
+        .bind(format!("{username}@example.com"))
+        .fetch_one(db)
+        .await
+        .unwrap();
+        let (_, key) = api_keys::create(db, user_id, "test", &["files:read".to_string()])
+            .await
+            .unwrap();
+        key
+    }
+
+    async fn session(subject: &str) -> Session {
+        let session = Session::new(None, Arc::new(MemorySessionStore::new()));
+        let principal = Principal {
+            subject: subject.to_string(),
+            roles: vec!["user".to_string()],
+            scopes: None,
+        };
+        session.insert(SESSION_KEY, principal).await.unwrap();
+        session
+    }
+
+    // 签名正确但已过期的访问令牌
+    fn expired_token(authenticator: &Authenticator) -> String {
+        let mut claims = authenticator
+            .tokens
+            .access_claims("alice@example.com".to_string(), AccessClaims::default());
+        claims.exp = Utc::now().timestamp() - 3600;
+        Keys::global().encode(&claims).unwrap()
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[sqlx::test]
+    async fn api_key_takes_precedence(db: Pool<Postgres>) {
+        let key = api_key(&db, "alice").await;
+        let authenticator = authenticator(db);
+        let session = session("bob").await;
+        let headers = headers(&[
+            (API_KEY_HEADER, &key),
+            ("authorization", "Bearer not-a-token"),
+        ]);
+
+        let principal = authenticator
+            .resolve(&headers, Some(&session))
+            .await
+            .unwrap();
+        assert_eq!(principal.subject, "alice");
+        assert_eq!(principal.scopes, Some(vec!["files:read".to_string()]));
+    }
+
+    #[sqlx::test]
+    async fn invalid_api_key_does_not_fall_through(db: Pool<Postgres>) {
+        let key = api_key(&db, "alice").await;
+        let authenticator = authenticator(db.clone());
+        let session = session("bob").await;
+
+        sqlx::query("UPDATE api_keys SET revoked_at = now()")
+            .execute(&db)
+            .await
+            .unwrap();
+        let unknown = format!("ak_{}", "0".repeat(64));
+        for key in [key.as_str(), unknown.as_str(), "wrong", ""] {
+            let headers = headers(&[(API_KEY_HEADER, key)]);
+            let response = authenticator
+                .resolve(&headers, Some(&session))
+                .await
+                .unwrap_err();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{key:?}");
+        }
+    }
+
+    #[sqlx::test]
+    async fn invalid_bearer_token_does_not_fall_through(db: Pool<Postgres>) {
+        let authenticator = authenticator(db);
+        let session = session("bob").await;
+        let expired = format!("Bearer {}", expired_token(&authenticator));
+
+        for value in ["Bearer not-a-token", "Basic YWxpY2U6c2VjcmV0", &expired] {
+            let headers = headers(&[("authorization", value)]);
+            let response = authenticator
+                .resolve(&headers, Some(&session))
+                .await
+                .unwrap_err();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{value:?}");
+        }
+    }
+
+    #[sqlx::test]
+    async fn falls_back_to_session(db: Pool<Postgres>) {
+        let authenticator = authenticator(db);
+        let session = session("bob").await;
+
+        let principal = authenticator
+            .resolve(&HeaderMap::new(), Some(&session))
+            .await
+            .unwrap();
+        assert_eq!(principal.subject, "bob");
+        assert_eq!(principal.scopes, None);
+    }
+
+    #[sqlx::test]
+    async fn no_credentials_is_unauthenticated(db: Pool<Postgres>) {
+        let authenticator = authenticator(db);
+        let empty = Session::new(None, Arc::new(MemorySessionStore::new()));
+
+        for session in [None, Some(&empty)] {
+            let response = authenticator
+                .resolve(&HeaderMap::new(), session)
+                .await
+                .unwrap_err();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[sqlx::test]
+    async fn middleware_inserts_principal(db: Pool<Postgres>) {
+        let key = api_key(&db, "alice").await;
+        let app = Router::new()
+            .route(
+                "/",
+                get(|Extension(principal): Extension<Principal>| async move { principal.subject }),
+            )
+            .route_layer(axum::middleware::from_fn_with_state(
+                authenticator(db),
+                require_auth,
+            ));
+
+        let request = Request::get("/")
+            .header(API_KEY_HEADER, &key)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"alice");
+
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{fmt::Display, marker::PhantomData};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::session::{Session, SessionError};
+
+/// Session 中保存当前身份的 key
+pub const SESSION_KEY: &str = "principal";
+
+/// 各角色可以申请的权限范围
+const ROLE_SCOPES: &[(&str, &[&str])] = &[
+    (
+        "user",
+        &[
+            "profile:read",
+            "profile:write",
+            "files:read",
+            "files:write",
+            "api-keys:write",
+        ],
+    ),
+    ("admin", &["users:read", "users:write"]),
+];
+
+/// 已认证的身份，可能来自 Session 或访问令牌
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Principal {
+    /// 用户名或令牌的 `sub`
+    pub subject: String,
+    /// 角色
+    pub roles: Vec<String>,
+    /// 权限范围；`None` 表示拥有角色允许的全部范围，如用户本人通过浏览器登录
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Principal {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.iter().any(|s| s == scope),
+            None => grant_scopes(&self.roles, None).iter().any(|s| s == scope),
+        }
+    }
+}
+
+/// 访问某个资源需要的权限
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Permission {
+    Role(String),
+    Scope(String),
+}
+
+impl Permission {
+    pub fn role(name: &str) -> Self {
+        Permission::Role(name.to_string())
+    }
+
+    pub fn scope(name: &str) -> Self {
+        Permission::Scope(name.to_string())
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Role(name) => write!(f, "role `{}`", name),
+            Permission::Scope(name) => write!(f, "scope `{}`", name),
+        }
+    }
+}
+
+/// 授权错误
+#[derive(Debug)]
+pub enum AuthzError {
+    /// 没有登录
+    Unauthenticated,
+    /// 缺少权限
+    Forbidden(Permission),
+    /// 读取 Session 失败
+    Session(SessionError),
+}
+
+impl Display for AuthzError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthzError::Unauthenticated => write!(f, "authentication required"),
+            AuthzError::Forbidden(permission) => write!(f, "missing {}", permission),
+            AuthzError::Session(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AuthzError {}
+
+impl From<SessionError> for AuthzError {
+    fn from(err: SessionError) -> Self {
+        AuthzError::Session(err)
+    }
+}
+
+impl IntoResponse for AuthzError {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        let (status, code, missing) = match self {
+            AuthzError::Session(err) => return err.into_response(),
+            AuthzError::Unauthenticated => (StatusCode::UNAUTHORIZED, "unauthenticated", None),
+            AuthzError::Forbidden(Permission::Role(name)) => (
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                Some(json!({"role": name})),
+            ),
+            AuthzError::Forbidden(Permission::Scope(name)) => (
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                Some(json!({"scope": name})),
+            ),
+        };
+        let body = Json(json!({
+            "status": "fail",
+            "code": code,
+            "message": message,
+            "missing": missing,
+        }));
+        (status, body).into_response()
+    }
+}
+
+/// 检查身份是否具有权限
+pub fn authorize(principal: &Principal, permission: &Permission) -> Result<(), AuthzError> {
+    let allowed = match permission {
+        Permission::Role(name) => principal.has_role(name),
+        Permission::Scope(name) => principal.has_scope(name),
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(AuthzError::Forbidden(permission.clone()))
+    }
+}
+
+/// 按角色筛选申请的权限范围（空格分隔），只保留角色允许的；`None` 表示授予全部允许的范围
+pub fn grant_scopes(roles: &[String], requested: Option<&str>) -> Vec<String> {
+    let allowed: Vec<&str> = ROLE_SCOPES
+        .iter()
+        .filter(|(role, _)| roles.iter().any(|r| r == role))
+        .flat_map(|(_, scopes)| scopes.iter().copied())
+        .collect();
+    let Some(requested) = requested else {
+        return allowed.into_iter().map(String::from).collect();
+    };
+    requested
+        .split_whitespace()
+        .filter(|scope| allowed.contains(scope))
+        .map(String::from)
+        .collect()
+}
+
+/// 当前请求的身份
+///
+/// 先从请求扩展中读取（由认证中间件放入），再从 Session 中读取。
+#[async_trait]
+impl<S> FromRequestParts<S> for Principal
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthzError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(principal) = parts.extensions.get::<Principal>() {
+            return Ok(principal.clone());
+        }
+        if let Some(session) = parts.extensions.get::<Session>() {
+            if let Some(principal) = session.get::<Principal>(SESSION_KEY).await? {
+                return Ok(principal);
+            }
+        }
+        Err(AuthzError::Unauthenticated)
+    }
+}
+
+/// 角色
+pub trait Role {
+    const NAME: &'static str;
+}
+
+/// 权限范围
+pub trait Scope {
+    const NAME: &'static str;
+}
+
+/// 管理员
+pub struct Admin;
+
+impl Role for Admin {
+    const NAME: &'static str = "admin";
+}
+
+/// 要求具有角色 `R`，否则返回 403
+pub struct RequireRole<R> {
+    pub principal: Principal,
+    _role: PhantomData<R>,
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    R: Role,
+    S: Send + Sync,
+{
+    type Rejection = AuthzError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = Principal::from_request_parts(parts, state).await?;
+        authorize(&principal, &Permission::role(R::NAME))?;
+        Ok(Self {
+            principal,
+            _role: PhantomData,
+        })
+    }
+}
+
+/// 要求具有权限范围 `P`，否则返回 403
+pub struct RequireScope<P> {
+    pub principal: Principal,
+    _scope: PhantomData<P>,
+}
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for RequireScope<P>
+where
+    P: Scope,
+    S: Send + Sync,
+{
+    type Rejection = AuthzError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = Principal::from_request_parts(parts, state).await?;
+        authorize(&principal, &Permission::scope(P::NAME))?;
+        Ok(Self {
+            principal,
+            _scope: PhantomData,
+        })
+    }
+}
+
+/// 路由级守卫，整组路由需要同一权限时使用：
+/// `route_layer(middleware::from_fn_with_state(Permission::role("admin"), authz::guard))`
+pub async fn guard(State(permission): State<Permission>, request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let result = match Principal::from_request_parts(&mut parts, &()).await {
+        Ok(principal) => authorize(&principal, &permission),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => next.run(Request::from_parts(parts, body)).await,
+        Err(err) => err.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(roles: &[&str], scopes: Option<&[&str]>) -> Principal {
+        Principal {
+            subject: "alice".to_string(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            scopes: scopes.map(|scopes| scopes.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn role_is_required() {
+        let admin = principal(&["user", "admin"], None);
+        assert!(authorize(&admin, &Permission::role("admin")).is_ok());
+
+        let user = principal(&["user"], None);
+        let err = authorize(&user, &Permission::role("admin")).unwrap_err();
+        assert!(
+            matches!(err, AuthzError::Forbidden(Permission::Role(ref name)) if name == "admin")
+        );
+    }
+
+    #[test]
+    fn scope_is_required() {
+        let token = principal(&["user"], Some(&["files:read"]));
+        assert!(authorize(&token, &Permission::scope("files:read")).is_ok());
+
+        let err = authorize(&token, &Permission::scope("files:write")).unwrap_err();
+        assert!(
+            matches!(err, AuthzError::Forbidden(Permission::Scope(ref name)) if name == "files:write")
+        );
+    }
+
+    #[test]
+    fn empty_scopes_deny_everything() {
+        let token = principal(&["user"], Some(&[]));
+        assert!(authorize(&token, &Permission::scope("profile:read")).is_err());
+    }
+
+    #[test]
+    fn unrestricted_principal_is_limited_to_role_scopes() {
+        let user = principal(&["user"], None);
+        assert!(authorize(&user, &Permission::scope("files:write")).is_ok());
+        assert!(authorize(&user, &Permission::scope("users:write")).is_err());
+        assert!(authorize(&user, &Permission::role("admin")).is_err());
+
+        let admin = principal(&["user", "admin"], None);
+        assert!(authorize(&admin, &Permission::scope("users:write")).is_ok());
+
+        let nobody = principal(&[], None);
+        assert!(authorize(&nobody, &Permission::scope("files:write")).is_err());
+    }
+
+    #[test]
+    fn forbidden_response_names_missing_permission() {
+        let response = AuthzError::Forbidden(Permission::scope("files:write")).into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = AuthzError::Unauthenticated.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn grants_requested_scopes_allowed_by_role() {
+        let roles = strings(&["user"]);
+        assert_eq!(
+            grant_scopes(&roles, Some("files:read users:write  profile:read")),
+            strings(&["files:read", "profile:read"])
+        );
+        assert_eq!(grant_scopes(&roles, Some("unknown")), Vec::<String>::new());
+        assert_eq!(grant_scopes(&roles, Some("")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn grants_all_allowed_scopes_when_none_requested() {
+        assert_eq!(
+            grant_scopes(&strings(&["user"]), None),
+            strings(&[
+                "profile:read",
+                "profile:write",
+                "files:read",
+                "files:write",
+                "api-keys:write"
+            ])
+        );
+        assert_eq!(
+            grant_scopes(&strings(&["user", "admin"]), Some("users:read files:read")),
+            strings(&["users:read", "files:read"])
+        );
+        assert_eq!(grant_scopes(&[], None), Vec::<String>::new());
+    }
+}
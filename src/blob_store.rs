@@ -0,0 +1,433 @@
+use std::{
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path as ObjectPath,
+    ObjectStore, WriteMultipart,
+};
+use serde_json::json;
+use tokio::{fs, io::AsyncReadExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::config::UploadConfig;
+
+/// 超过这个大小的文件用分片上传
+const MULTIPART_THRESHOLD: u64 = 8 * 1024 * 1024;
+/// 分片上传时每次从文件读取的字节数
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// 读取到的对象
+pub struct Blob {
+    /// 字节数
+    pub size: u64,
+    /// 内容
+    pub stream: BoxStream<'static, io::Result<Bytes>>,
+}
+
+/// 存储错误
+#[derive(Debug)]
+pub enum BlobError {
+    /// 对象不存在
+    NotFound(String),
+    /// key 不合法，如包含路径分隔符
+    InvalidKey(String),
+    /// 本地文件读写失败
+    Io(io::Error),
+    /// 对象存储请求失败
+    ObjectStore(object_store::Error),
+}
+
+impl Display for BlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlobError::NotFound(key) => write!(f, "blob `{}` not found", key),
+            BlobError::InvalidKey(key) => write!(f, "invalid blob key {:?}", key),
+            BlobError::Io(err) => write!(f, "io error: {}", err),
+            BlobError::ObjectStore(err) => write!(f, "object store error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BlobError {}
+
+impl From<io::Error> for BlobError {
+    fn from(err: io::Error) -> Self {
+        BlobError::Io(err)
+    }
+}
+
+impl From<object_store::Error> for BlobError {
+    fn from(err: object_store::Error) -> Self {
+        match err {
+            object_store::Error::NotFound { path, .. } => BlobError::NotFound(path),
+            err => BlobError::ObjectStore(err),
+        }
+    }
+}
+
+impl IntoResponse for BlobError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = match &self {
+            BlobError::NotFound(_) | BlobError::InvalidKey(_) => {
+                (StatusCode::NOT_FOUND, "not_found", "file not found")
+            }
+            _ => {
+                tracing::error!("{}", self);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal Server Error",
+                )
+            }
+        };
+        let body = Json(json!({
+            "status": if status.is_server_error() { "error" } else { "fail" },
+            "code": code,
+            "message": message,
+        }));
+        (status, body).into_response()
+    }
+}
+
+/// 保存上传文件的存储后端
+///
+/// key 由调用方生成，只能是单个文件名，不能包含路径分隔符，也不能以 `.` 开头。
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// 把本地文件保存为 `key`；调用方之后可以删除本地文件
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), BlobError>;
+
+    /// 保存小对象，如元数据
+    async fn put_bytes(&self, key: &str, bytes: Bytes) -> Result<(), BlobError>;
+
+    /// 读取对象
+    async fn get(&self, key: &str) -> Result<Blob, BlobError>;
+
+    /// 删除对象，不存在时不报错
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+
+    /// 把对象完整读到内存，只用于小对象
+    async fn get_bytes(&self, key: &str) -> Result<Bytes, BlobError> {
+        let blob = self.get(key).await?;
+        let chunks: Vec<Bytes> = blob.stream.try_collect().await?;
+        Ok(chunks.concat().into())
+    }
+}
+
+/// 按配置创建存储后端
+pub fn from_config(config: &UploadConfig) -> Result<Arc<dyn BlobStore>, BlobError> {
+    match config.backend.as_str() {
+        "s3" => Ok(Arc::new(S3BlobStore::new(config)?)),
+        _ => Ok(Arc::new(LocalBlobStore::new(&config.dir))),
+    }
+}
+
+// key 只能是单个文件名
+fn check_key(key: &str) -> Result<(), BlobError> {
+    let valid = !key.is_empty()
+        && !key.starts_with('.')
+        && !key.contains(['/', '\\', '\0'])
+        && key.len() <= 255;
+    if !valid {
+        return Err(BlobError::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+/// 保存在本地目录中
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, BlobError> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    /// 通过重命名保存，本地文件需要和保存目录在同一个文件系统中
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), BlobError> {
+        fs::rename(path, self.path(key)?).await?;
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, bytes: Bytes) -> Result<(), BlobError> {
+        let path = self.path(key)?;
+        // key 不能以 `.` 开头，所以临时文件不会和已有对象重名
+        let temp = self.root.join(format!(".{}.part", Uuid::new_v4().simple()));
+        fs::write(&temp, &bytes).await?;
+        if let Err(err) = fs::rename(&temp, &path).await {
+            let _ = fs::remove_file(&temp).await;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Blob, BlobError> {
+        let file = match fs::File::open(self.path(key)?).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(BlobError::NotFound(key.to_string()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Err(BlobError::NotFound(key.to_string()));
+        }
+        Ok(Blob {
+            size: metadata.len(),
+            stream: ReaderStream::new(file).boxed(),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 保存在 S3 兼容的对象存储中，如 AWS S3、MinIO
+pub struct S3BlobStore {
+    client: AmazonS3,
+}
+
+impl S3BlobStore {
+    pub fn new(config: &UploadConfig) -> Result<Self, BlobError> {
+        let s3 = &config.s3;
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(&s3.bucket)
+            .with_region(&s3.region)
+            .with_access_key_id(&s3.access_key_id)
+            .with_secret_access_key(&s3.secret_access_key);
+        if !s3.endpoint.is_empty() {
+            // MinIO 等自建服务通常不支持虚拟主机风格的地址
+            builder = builder
+                .with_endpoint(&s3.endpoint)
+                .with_allow_http(s3.endpoint.starts_with("http://"))
+                .with_virtual_hosted_style_request(false);
+        }
+        Ok(Self {
+            client: builder.build()?,
+        })
+    }
+
+    fn path(&self, key: &str) -> Result<ObjectPath, BlobError> {
+        check_key(key)?;
+        Ok(ObjectPath::from(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    /// 小文件一次上传，大文件分片上传
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), BlobError> {
+        let location = self.path(key)?;
+        let mut file = fs::File::open(path).await?;
+        if file.metadata().await?.len() <= MULTIPART_THRESHOLD {
+            let mut bytes = vec![];
+            file.read_to_end(&mut bytes).await?;
+            self.client.put(&location, bytes.into()).await?;
+            return Ok(());
+        }
+
+        let upload = self.client.put_multipart(&location).await?;
+        let mut writer = WriteMultipart::new(upload);
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        loop {
+            let n = match file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) => {
+                    let _ = writer.abort().await;
+                    return Err(err.into());
+                }
+            };
+            writer.write(&buffer[..n]);
+            // 限制同时上传的分片数量，避免整个文件进入内存
+            writer.wait_for_capacity(4).await?;
+        }
+        writer.finish().await?;
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, bytes: Bytes) -> Result<(), BlobError> {
+        self.client.put(&self.path(key)?, bytes.into()).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Blob, BlobError> {
+        let result = self.client.get(&self.path(key)?).await?;
+        Ok(Blob {
+            size: result.meta.size as u64,
+            stream: result.into_stream().map_err(io::Error::other).boxed(),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match self.client.delete(&self.path(key)?).await {
+            Err(object_store::Error::NotFound { .. }) | Ok(()) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_plain_file_names() {
+        for key in [
+            "0123456789abcdef0123456789abcdef",
+            "a.json",
+            "1715159123_测试.pdf",
+        ] {
+            assert!(check_key(key).is_ok(), "{key:?}");
+        }
+        assert!(check_key(&"a".repeat(255)).is_ok());
+    }
+
+    #[test]
+    fn rejects_unsafe_keys() {
+        let long = "a".repeat(256);
+        for key in [
+            "",
+            ".",
+            "..",
+            ".hidden",
+            ".abc.part",
+            "a/b",
+            // `1_..%2F..%2Fetc%2Fpasswd` 解码后的结果
+            "1_../../etc/passwd",
+            "/etc/passwd",
+            "a\\b",
+            "..\\windows",
+            "a\0b",
+            &long,
+        ] {
+            assert!(
+                matches!(check_key(key), Err(BlobError::InvalidKey(_))),
+                "{key:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn local_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalBlobStore::new(dir.path());
+
+        store
+            .put_bytes("a.json", Bytes::from_static(b"{}"))
+            .await
+            .unwrap();
+        let blob = store.get("a.json").await.unwrap();
+        assert_eq!(blob.size, 2);
+        assert_eq!(store.get_bytes("a.json").await.unwrap(), "{}");
+
+        // 覆盖已有对象，且不留下临时文件
+        store
+            .put_bytes("a.json", Bytes::from_static(b"[1]"))
+            .await
+            .unwrap();
+        assert_eq!(store.get_bytes("a.json").await.unwrap(), "[1]");
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["a.json"]);
+
+        store.delete("a.json").await.unwrap();
+        assert!(matches!(
+            store.get("a.json").await,
+            Err(BlobError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn put_file_moves_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalBlobStore::new(dir.path());
+        let source = dir.path().join(".upload.part");
+        std::fs::write(&source, b"hello").unwrap();
+
+        store.put_file("hello", &source).await.unwrap();
+        assert!(!source.exists());
+        assert_eq!(store.get_bytes("hello").await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn missing_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalBlobStore::new(dir.path());
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+
+        assert!(matches!(
+            store.get("missing").await,
+            Err(BlobError::NotFound(_))
+        ));
+        // 目录不是对象
+        assert!(matches!(
+            store.get("sub").await,
+            Err(BlobError::NotFound(_))
+        ));
+        assert!(store.delete("missing").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn local_store_checks_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(dir.path().join("secret"), b"secret").unwrap();
+        let store = LocalBlobStore::new(&root);
+
+        assert!(matches!(
+            store.get("../secret").await,
+            Err(BlobError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            store.delete("../secret").await,
+            Err(BlobError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            store.put_bytes("../secret", Bytes::new()).await,
+            Err(BlobError::InvalidKey(_))
+        ));
+        assert_eq!(std::fs::read(dir.path().join("secret")).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn invalid_keys_look_like_missing_files() {
+        for err in [
+            BlobError::InvalidKey("../secret".to_string()),
+            BlobError::NotFound("missing".to_string()),
+        ] {
+            assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::async_trait;
+use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::config::{AppConfig, CacheConfig};
+use crate::redis_client::{Codec, RedisPool, RedisStoreError};
+
+/// 缓存条目，记录写入时间用于判断是否新鲜
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    value: T,
+    cached_at: u64,
+}
+
+/// 缓存存储，保存编码后的缓存条目
+#[async_trait]
+pub trait CacheStore: Send + Sync + 'static {
+    /// 读取，不存在或已过期时返回 `None`
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, RedisStoreError>;
+    /// 写入，`ttl` 秒后过期
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: u64) -> Result<(), RedisStoreError>;
+    /// 删除
+    async fn delete(&self, key: &str) -> Result<(), RedisStoreError>;
+    /// key 使用的编码方式
+    fn codec_for(&self, _key: &str) -> Codec {
+        Codec::Json
+    }
+}
+
+/// 保存在 Redis 中的缓存，编码方式按 key 前缀决定
+#[derive(Clone)]
+pub struct RedisCacheStore {
+    redis: RedisPool,
+}
+
+impl RedisCacheStore {
+    pub fn new(redis: RedisPool) -> Self {
+        Self { redis }
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, RedisStoreError> {
+        let mut conn = self.redis.get();
+        Ok(conn.get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: u64) -> Result<(), RedisStoreError> {
+        let mut conn = self.redis.get();
+        conn.set_ex::<_, _, ()>(key, value, ttl).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), RedisStoreError> {
+        self.redis.delete(key).await?;
+        Ok(())
+    }
+
+    fn codec_for(&self, key: &str) -> Codec {
+        self.redis.codec_for(key)
+    }
+}
+
+// 编码后的值和过期时间
+type MemoryEntry = (Vec<u8>, Instant);
+
+/// 保存在内存中的缓存，只适合单进程和测试
+#[derive(Clone, Default)]
+pub struct MemoryCacheStore {
+    entries: Arc<Mutex<HashMap<String, MemoryEntry>>>,
+}
+
+impl MemoryCacheStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, RedisStoreError> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((value, expires_at)) if *expires_at > Instant::now() => Ok(Some(value.clone())),
+            Some(_) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: u64) -> Result<(), RedisStoreError> {
+        let expires_at = Instant::now() + Duration::from_secs(ttl);
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), (value, expires_at));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), RedisStoreError> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// 缓存命中统计
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct CacheStats {
+    /// 命中且未过期
+    pub hits: u64,
+    /// 未命中，回源加载
+    pub misses: u64,
+    /// 命中但已不新鲜，返回旧值并在后台刷新
+    pub stale: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale: AtomicU64,
+}
+
+/// 读穿透缓存
+///
+/// 先查缓存（通常是 Redis），未命中时回源加载并写回缓存。同一个 key 同时只有一个请求回源，
+/// 其它请求等待它写回后直接读缓存，避免冷 key 被并发击穿。
+/// 超过新鲜期的缓存会先返回旧值，同时在后台刷新。
+/// 缓存不可用时直接回源，不影响请求。
+#[derive(Clone)]
+pub struct ReadThroughCache {
+    store: Arc<dyn CacheStore>,
+    ttl_secs: u64,
+    fresh_secs: u64,
+    counters: Arc<Counters>,
+    locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+impl ReadThroughCache {
+    /// 按全局配置创建缓存
+    pub fn from_config(redis: RedisPool) -> Self {
+        Self::new(redis, &AppConfig::global().cache)
+    }
+
+    pub fn new(redis: RedisPool, config: &CacheConfig) -> Self {
+        Self::with_store(RedisCacheStore::new(redis), config)
+    }
+
+    /// 使用指定的存储
+    pub fn with_store(store: impl CacheStore, config: &CacheConfig) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl_secs: config.ttl_secs,
+            fresh_secs: config.fresh_secs,
+            counters: Arc::new(Counters::default()),
+            locks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 当前的命中统计
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            stale: self.counters.stale.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 读取缓存，未命中时调用 `load` 回源
+    ///
+    /// `load` 返回 `None` 表示记录不存在，不会写入缓存。
+    pub async fn get_or_load<T, E, F, Fut>(&self, key: &str, load: F) -> Result<Option<T>, E>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+        E: Display + Send + 'static,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Option<T>, E>> + Send,
+    {
+        if let Some(entry) = self.read::<T>(key).await {
+            if now() < entry.cached_at + self.fresh_secs {
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.counters.stale.fetch_add(1, Ordering::Relaxed);
+                self.revalidate(key, load);
+            }
+            return Ok(Some(entry.value));
+        }
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+
+        let lock = self.lock_for(key);
+        let result = async {
+            let _guard = lock.lock().await;
+            // 等锁期间其它请求可能已经回填
+            if let Some(entry) = self.read::<T>(key).await {
+                return Ok(Some(entry.value));
+            }
+            let value = load().await?;
+            if let Some(value) = &value {
+                self.write(key, value).await;
+            }
+            Ok(value)
+        }
+        .await;
+        self.release(key, lock);
+        result
+    }
+
+    /// 删除缓存
+    ///
+    /// 会等待该 key 上正在进行的回源完成后再删除，避免回源读到的旧值在删除之后才写回。
+    pub async fn invalidate(&self, keys: &[String]) {
+        for key in keys {
+            let lock = self.lock_for(key);
+            {
+                let _guard = lock.lock().await;
+                if let Err(err) = self.store.delete(key).await {
+                    tracing::error!("failed to invalidate cache {}: {}", key, err);
+                }
+            }
+            self.release(key, lock);
+        }
+    }
+
+    // 后台刷新；已经有请求在回源或刷新时跳过
+    fn revalidate<T, E, F, Fut>(&self, key: &str, load: F)
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+        E: Display + Send + 'static,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Option<T>, E>> + Send,
+    {
+        let lock = self.lock_for(key);
+        let Ok(guard) = lock.clone().try_lock_owned() else {
+            self.release(key, lock);
+            return;
+        };
+        let cache = self.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            match load().await {
+                Ok(Some(value)) => cache.write(&key, &value).await,
+                // 记录已经不存在
+                Ok(None) => {
+                    if let Err(err) = cache.store.delete(&key).await {
+                        tracing::warn!("failed to delete cache {}: {}", key, err);
+                    }
+                }
+                Err(err) => tracing::warn!("failed to refresh cache {}: {}", key, err),
+            }
+            drop(guard);
+            cache.release(&key, lock);
+        });
+    }
+
+    // 读取缓存；存储出错或数据无法解码时按未命中处理
+    async fn read<T: DeserializeOwned>(&self, key: &str) -> Option<Entry<T>> {
+        let codec = self.store.codec_for(key);
+        let entry = match self.store.get(key).await {
+            Ok(bytes) => bytes.map(|bytes| codec.decode(&bytes)).transpose(),
+            Err(err) => Err(err),
+        };
+        match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!("failed to read cache {}: {}", key, err);
+                None
+            }
+        }
+    }
+
+    async fn write<T: Serialize>(&self, key: &str, value: &T) {
+        let entry = Entry {
+            value,
+            cached_at: now(),
+        };
+        let result = match self.store.codec_for(key).encode(&entry) {
+            Ok(bytes) => self.store.set(key, bytes, self.ttl_secs).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::warn!("failed to write cache {}: {}", key, err);
+        }
+    }
+
+    // key 对应的锁，不存在时创建
+    fn lock_for(&self, key: &str) -> Arc<AsyncMutex<()>> {
+        let mut locks = self.locks.lock().unwrap();
+        locks.entry(key.to_string()).or_default().clone()
+    }
+
+    // 释放对锁的引用，没有其它请求持有时从表中移除
+    fn release(&self, key: &str, lock: Arc<AsyncMutex<()>>) {
+        let mut locks = self.locks.lock().unwrap();
+        // 表中一份 + 这里一份
+        if Arc::strong_count(&lock) == 2 {
+            locks.remove(key);
+        }
+    }
+}
+
+// 当前的 Unix 时间戳（秒）
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    fn cache(fresh_secs: u64) -> ReadThroughCache {
+        let config = CacheConfig {
+            ttl_secs: 60,
+            fresh_secs,
+        };
+        ReadThroughCache::with_store(MemoryCacheStore::new(), &config)
+    }
+
+    // 等待后台任务完成
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_load_once() {
+        let cache = cache(60);
+        let loads = Arc::new(AtomicUsize::new(0));
+        let requests = (0..10).map(|_| {
+            let cache = cache.clone();
+            let loads = loads.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_load("account:1", move || async move {
+                        loads.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        Ok::<_, String>(Some("alice".to_string()))
+                    })
+                    .await
+            })
+        });
+        for result in futures::future::join_all(requests).await {
+            assert_eq!(result.unwrap().unwrap().as_deref(), Some("alice"));
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.stats().misses, 10);
+
+        // 回填后直接命中
+        let value = cache
+            .get_or_load("account:1", || async { Err::<Option<String>, _>("unused") })
+            .await;
+        assert_eq!(value.unwrap().as_deref(), Some("alice"));
+        assert_eq!(cache.stats().hits, 1);
+        assert!(cache.locks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn serves_stale_value_while_refreshing() {
+        // 新鲜期为 0，写入后立即变旧
+        let cache = cache(0);
+        let value = cache
+            .get_or_load("account:1", || async { Ok::<_, String>(Some(1)) })
+            .await;
+        assert_eq!(value.unwrap(), Some(1));
+
+        let refreshing = Arc::new(Notify::new());
+        let loads = Arc::new(AtomicUsize::new(0));
+        let load = |refreshing: Arc<Notify>, loads: Arc<AtomicUsize>| {
+            move || async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                refreshing.notified().await;
+                Ok::<_, String>(Some(2))
+            }
+        };
+
+        // 返回旧值，刷新在后台等待
+        let value = cache
+            .get_or_load("account:1", load(refreshing.clone(), loads.clone()))
+            .await;
+        assert_eq!(value.unwrap(), Some(1));
+        settle().await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // 刷新进行中，不会再启动一次刷新
+        let value = cache
+            .get_or_load("account:1", load(refreshing.clone(), loads.clone()))
+            .await;
+        assert_eq!(value.unwrap(), Some(1));
+        settle().await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.stats().stale, 2);
+
+        // 刷新完成后读到新值
+        refreshing.notify_one();
+        settle().await;
+        let value = cache
+            .get_or_load("account:1", || async { Ok::<_, String>(Some(3)) })
+            .await;
+        assert_eq!(value.unwrap(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn invalidate_waits_for_in_flight_load() {
+        let cache = cache(60);
+        let loading = Arc::new(Notify::new());
+        let loader = tokio::spawn({
+            let cache = cache.clone();
+            let loading = loading.clone();
+            async move {
+                cache
+                    .get_or_load("account:1", move || async move {
+                        loading.notified().await;
+                        Ok::<_, String>(Some("old".to_string()))
+                    })
+                    .await
+            }
+        });
+        settle().await;
+
+        let invalidated = Arc::new(AtomicUsize::new(0));
+        let invalidate = tokio::spawn({
+            let cache = cache.clone();
+            let invalidated = invalidated.clone();
+            async move {
+                cache.invalidate(&["account:1".to_string()]).await;
+                invalidated.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        settle().await;
+        // 回源还没写回，删除必须等待
+        assert_eq!(invalidated.load(Ordering::SeqCst), 0);
+
+        loading.notify_one();
+        assert_eq!(loader.await.unwrap().unwrap().as_deref(), Some("old"));
+        invalidate.await.unwrap();
+
+        // 回源写回的旧值已被删除，下次读取重新回源
+        let value = cache
+            .get_or_load("account:1", || async {
+                Ok::<_, String>(Some("new".to_string()))
+            })
+            .await;
+        assert_eq!(value.unwrap().as_deref(), Some("new"));
+    }
+
+    #[tokio::test]
+    async fn missing_records_are_not_cached() {
+        let cache = cache(60);
+        let value = cache
+            .get_or_load("account:404", || async {
+                Ok::<Option<String>, String>(None)
+            })
+            .await;
+        assert_eq!(value.unwrap(), None);
+
+        let value = cache
+            .get_or_load("account:404", || async {
+                Ok::<_, String>(Some("created".to_string()))
+            })
+            .await;
+        assert_eq!(value.unwrap().as_deref(), Some("created"));
+        assert_eq!(cache.stats().misses, 2);
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt::Display;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+/// 请求中的 Cookie
+///
+/// 按 RFC 6265 解析所有 `Cookie` 头：支持双引号包裹的值、百分号编码，
+/// 同名 Cookie 按出现顺序全部保留（浏览器会把路径更具体的排在前面）。
+/// 头部格式错误时返回 400。
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+/// `Cookie` 头格式错误
+#[derive(Debug)]
+pub struct MalformedCookie(String);
+
+impl Display for MalformedCookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed Cookie header: {}", self.0)
+    }
+}
+
+impl std::error::Error for MalformedCookie {}
+
+impl IntoResponse for MalformedCookie {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "status": "fail",
+            "code": "malformed_cookie",
+            "message": self.to_string(),
+        }));
+        (StatusCode::BAD_REQUEST, body).into_response()
+    }
+}
+
+impl CookieJar {
+    /// 解析请求头中的所有 `Cookie` 头
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, MalformedCookie> {
+        let mut cookies = vec![];
+        for value in headers.get_all(header::COOKIE) {
+            let value = value
+                .to_str()
+                .map_err(|_| MalformedCookie("contains non-ASCII characters".to_string()))?;
+            cookies.extend(parse(value)?);
+        }
+        Ok(Self { cookies })
+    }
+
+    /// 第一个同名 Cookie 的值
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 所有同名 Cookie 的值，按出现顺序
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.cookies
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 所有 Cookie 的名称和值
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CookieJar
+where
+    S: Send + Sync,
+{
+    type Rejection = MalformedCookie;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        CookieJar::from_headers(&parts.headers)
+    }
+}
+
+// 解析一个 `Cookie` 头：`name=value; name2=value2`
+fn parse(header: &str) -> Result<Vec<(String, String)>, MalformedCookie> {
+    let mut cookies = vec![];
+    for pair in header.split(';') {
+        let pair = pair.trim();
+        // 允许末尾多余的分号
+        if pair.is_empty() {
+            continue;
+        }
+        // 值中可以有 `=`（如 base64 填充），只按第一个 `=` 分割
+        let Some((name, value)) = pair.split_once('=') else {
+            return Err(MalformedCookie(format!("{:?} has no `=`", pair)));
+        };
+        let name = name.trim();
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(MalformedCookie(format!("invalid cookie name {:?}", name)));
+        }
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(inner) => inner
+                .strip_suffix('"')
+                .ok_or_else(|| MalformedCookie(format!("unterminated quote in {:?}", name)))?,
+            None => value,
+        };
+        if value.contains('"') {
+            return Err(MalformedCookie(format!("unexpected quote in {:?}", name)));
+        }
+        let value = percent_decode(value)
+            .ok_or_else(|| MalformedCookie(format!("value of {:?} is not valid UTF-8", name)))?;
+        cookies.push((name.to_string(), value));
+    }
+    Ok(cookies)
+}
+
+// RFC 7230 的 token 字符
+fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// 百分号解码；不完整的 `%XX` 按原样保留，解码结果必须是 UTF-8
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                decoded.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn from_values(values: &[&str]) -> Result<CookieJar, MalformedCookie> {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header::COOKIE, HeaderValue::from_str(value).unwrap());
+        }
+        CookieJar::from_headers(&headers)
+    }
+
+    #[test]
+    fn parses_pairs() {
+        let jar = from_values(&["a=1; b=2;c=3"]).unwrap();
+        let cookies: Vec<_> = jar.iter().collect();
+        assert_eq!(cookies, vec![("a", "1"), ("b", "2"), ("c", "3")]);
+        assert_eq!(jar.get("missing"), None);
+    }
+
+    #[test]
+    fn strips_quotes() {
+        let jar = from_values(&[r#"a="hello world"; b="""#]).unwrap();
+        assert_eq!(jar.get("a"), Some("hello world"));
+        assert_eq!(jar.get("b"), Some(""));
+    }
+
+    #[test]
+    fn decodes_percent_encoding() {
+        let jar =
+            from_values(&["name=%E5%BC%A0%E4%B8%89; partial=100%; bad=%zz; token=abc=="]).unwrap();
+        assert_eq!(jar.get("name"), Some("张三"));
+        assert_eq!(jar.get("partial"), Some("100%"));
+        assert_eq!(jar.get("bad"), Some("%zz"));
+        assert_eq!(jar.get("token"), Some("abc=="));
+    }
+
+    #[test]
+    fn keeps_duplicates_in_order() {
+        let jar = from_values(&["sid=first; other=x", "sid=second"]).unwrap();
+        assert_eq!(jar.get("sid"), Some("first"));
+        assert_eq!(
+            jar.get_all("sid").collect::<Vec<_>>(),
+            vec!["first", "second"]
+        );
+    }
+
+    #[test]
+    fn skips_empty_pairs() {
+        let jar = from_values(&["; a=1;; b=2; "]).unwrap();
+        assert_eq!(jar.iter().count(), 2);
+        assert_eq!(jar.get("a"), Some("1"));
+        assert_eq!(jar.get("b"), Some("2"));
+        assert_eq!(jar.get("empty"), None);
+
+        let jar = from_values(&["empty="]).unwrap();
+        assert_eq!(jar.get("empty"), Some(""));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for value in [
+            "novalue",
+            "=1",
+            "bad name=1",
+            "a=\"unterminated",
+            "a=in\"side",
+            "a=%FF",
+        ] {
+            assert!(
+                from_values(&[value]).is_err(),
+                "{:?} should be rejected",
+                value
+            );
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_bytes(b"a=\xe5").unwrap());
+        assert!(CookieJar::from_headers(&headers).is_err());
+    }
+
+    #[test]
+    fn malformed_cookie_is_bad_request() {
+        let response = from_values(&["novalue"]).unwrap_err().into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
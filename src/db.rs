@@ -1,7 +1,15 @@
-use std::{env, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
-use dotenv::dotenv;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    postgres::PgPoolOptions,
+    Pool, Postgres,
+};
+
+use crate::config::AppConfig;
+
+/// 编译时嵌入 `migrations/` 目录下的所有迁移
+pub static MIGRATOR: Migrator = sqlx::migrate!();
 
 #[derive(Debug)]
 pub struct AppState {
@@ -9,11 +17,17 @@ pub struct AppState {
 }
 
 pub async fn init_db() -> Arc<AppState> {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set.");
+    return Arc::new(AppState {
+        db: connect().await,
+    });
+}
+
+/// 按全局配置连接数据库并检查迁移，失败时退出
+pub async fn connect() -> Pool<Postgres> {
+    let config = &AppConfig::global().postgres;
     let pool = match PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
+        .max_connections(config.max_connections)
+        .connect(&config.dsn)
         .await
     {
         Ok(pool) => {
@@ -26,5 +40,143 @@ pub async fn init_db() -> Arc<AppState> {
         }
     };
 
-    return Arc::new(AppState { db: pool.clone() });
+    ensure_migrated(&pool).await;
+    pool
+}
+
+/// 单个迁移的状态
+#[derive(Debug)]
+pub enum MigrationState {
+    /// 已执行
+    Applied,
+    /// 未执行
+    Pending,
+    /// 已执行，但迁移文件在执行后被修改过
+    Changed,
+}
+
+/// 迁移状态报告中的一行
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// 执行所有未执行的迁移
+pub async fn migrate_up(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// 回滚最近一次执行的迁移，返回被回滚的版本
+pub async fn migrate_down(pool: &Pool<Postgres>) -> Result<Option<i64>, MigrateError> {
+    let mut applied: Vec<i64> = applied_migrations(pool).await?.into_keys().collect();
+    applied.sort_unstable();
+    let Some(latest) = applied.pop() else {
+        return Ok(None);
+    };
+    // undo 会回滚所有版本大于 target 的迁移
+    let target = applied.pop().unwrap_or(0);
+    MIGRATOR.undo(pool, target).await?;
+    Ok(Some(latest))
+}
+
+/// 所有迁移的执行状态
+pub async fn migration_status(pool: &Pool<Postgres>) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let applied = applied_migrations(pool).await?;
+    let status = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| {
+            let state = match applied.get(&m.version) {
+                Some(checksum) if checksum.as_slice() == m.checksum.as_ref() => {
+                    MigrationState::Applied
+                }
+                Some(_) => MigrationState::Changed,
+                None => MigrationState::Pending,
+            };
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                state,
+            }
+        })
+        .collect();
+    Ok(status)
+}
+
+/// 启动前检查迁移
+///
+/// 开发模式下自动执行未执行的迁移；生产模式下存在未执行的迁移时拒绝启动，
+/// 需要先通过 `migrate up` 子命令显式执行。
+pub async fn ensure_migrated(pool: &Pool<Postgres>) {
+    let pending: Vec<MigrationStatus> = match migration_status(pool).await {
+        Ok(status) => status
+            .into_iter()
+            .filter(|s| !matches!(s.state, MigrationState::Applied))
+            .collect(),
+        Err(err) => {
+            println!("🔥 Failed to read migration status: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if pending.is_empty() {
+        return;
+    }
+
+    if AppConfig::global().is_production() {
+        println!("🔥 Refusing to start: the database schema is not up to date.");
+        for s in &pending {
+            println!("   {:?}\t{}\t{}", s.state, s.version, s.description);
+        }
+        println!("   Run the `migrate up` subcommand first.");
+        std::process::exit(1);
+    }
+
+    match migrate_up(pool).await {
+        Ok(()) => println!("✅Applied {} pending migration(s)", pending.len()),
+        Err(err) => {
+            println!("🔥 Failed to run migrations: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `migrate` 子命令：`migrate up`、`migrate down`、`migrate status`
+pub async fn migrate_command(pool: &Pool<Postgres>, args: &[String]) -> Result<(), MigrateError> {
+    match args.first().map(String::as_str) {
+        Some("up") => {
+            migrate_up(pool).await?;
+            println!("✅Database is up to date");
+        }
+        Some("down") => match migrate_down(pool).await? {
+            Some(version) => println!("✅Reverted migration {}", version),
+            None => println!("No migration to revert"),
+        },
+        Some("status") | None => {
+            for s in migration_status(pool).await? {
+                println!("{:?}\t{}\t{}", s.state, s.version, s.description);
+            }
+        }
+        Some(other) => {
+            println!("Unknown migrate command: {}", other);
+            println!("Usage: migrate [up|down|status]");
+            // 用法错误，非零退出码让脚本能发现
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
+
+// 已执行的迁移：版本 -> 校验和
+async fn applied_migrations(pool: &Pool<Postgres>) -> Result<HashMap<i64, Vec<u8>>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect();
+    Ok(applied)
 }
@@ -0,0 +1,289 @@
+use std::fmt::Display;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+/// Postgres 的 SQLSTATE：违反唯一约束
+const UNIQUE_VIOLATION: &str = "23505";
+/// Postgres 的 SQLSTATE：违反外键约束
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+/// Postgres 的 SQLSTATE：违反 CHECK 约束
+const CHECK_VIOLATION: &str = "23514";
+
+/// 应用统一的错误类型
+///
+/// 响应体为 `{"status": "fail", "code": "...", "message": "..."}`，
+/// 其中 `code` 是稳定的、可供程序判断的错误码。
+#[derive(Debug)]
+pub enum AppError {
+    /// 请求参数错误
+    BadRequest(String),
+    /// 未认证或认证失败
+    Unauthorized(String),
+    /// 资源不存在
+    NotFound(String),
+    /// 与当前状态冲突
+    Conflict(String),
+    /// 请求格式正确但无法处理
+    UnprocessableEntity(String),
+    /// 请求体超过限制
+    PayloadTooLarge(String),
+    /// 违反唯一约束（23505）
+    UniqueViolation(String),
+    /// 违反外键约束，引用的记录不存在（23503）
+    ForeignKeyViolation(String),
+    /// 违反 CHECK 约束（23514）
+    CheckViolation(String),
+    /// 其他数据库错误
+    Database(sqlx::Error),
+    /// 其他服务端错误
+    Internal(String),
+    /// Redis 错误
+    Redis(redis::RedisError),
+    /// JWT 错误
+    Jwt(jsonwebtoken::errors::Error),
+}
+
+impl AppError {
+    /// HTTP 状态码
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::NotFound(_) | AppError::ForeignKeyViolation(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) | AppError::UniqueViolation(_) => StatusCode::CONFLICT,
+            AppError::UnprocessableEntity(_) | AppError::CheckViolation(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Redis(err) => {
+                if err.is_io_error()
+                    || err.is_connection_refusal()
+                    || err.is_connection_dropped()
+                    || err.is_timeout()
+                {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }
+            AppError::Jwt(_) => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// 机器可读的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::UnprocessableEntity(_) => "unprocessable_entity",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::UniqueViolation(_) => "unique_violation",
+            AppError::ForeignKeyViolation(_) => "foreign_key_violation",
+            AppError::CheckViolation(_) => "check_violation",
+            AppError::Database(_) => "database_error",
+            AppError::Internal(_) => "internal_error",
+            AppError::Redis(_) => "redis_error",
+            AppError::Jwt(_) => "invalid_token",
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::UnprocessableEntity(msg)
+            | AppError::PayloadTooLarge(msg)
+            | AppError::UniqueViolation(msg)
+            | AppError::ForeignKeyViolation(msg)
+            | AppError::CheckViolation(msg) => write!(f, "{}", msg),
+            AppError::Database(err) => write!(f, "database error: {}", err),
+            AppError::Internal(msg) => write!(f, "internal error: {}", msg),
+            AppError::Redis(err) => write!(f, "redis error: {}", err),
+            AppError::Jwt(err) => write!(f, "invalid token: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        if let sqlx::Error::RowNotFound = err {
+            return AppError::NotFound("Record not found".to_string());
+        }
+        if let Some(db_err) = err.as_database_error() {
+            let message = db_err.message().to_string();
+            match db_err.code().as_deref() {
+                Some(UNIQUE_VIOLATION) => return AppError::UniqueViolation(message),
+                Some(FOREIGN_KEY_VIOLATION) => return AppError::ForeignKeyViolation(message),
+                Some(CHECK_VIOLATION) => return AppError::CheckViolation(message),
+                _ => {}
+            }
+        }
+        AppError::Database(err)
+    }
+}
+
+impl From<redis::RedisError> for AppError {
+    fn from(err: redis::RedisError) -> Self {
+        AppError::Redis(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        AppError::Jwt(err)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        // 服务端错误只记录日志，不把内部细节返回给客户端
+        let (status_text, message) = if status.is_server_error() {
+            tracing::error!("{}", self);
+            let reason = status.canonical_reason().unwrap_or("Internal Server Error");
+            ("error", reason.to_string())
+        } else {
+            ("fail", self.to_string())
+        };
+        let body = Json(json!({
+            "status": status_text,
+            "code": self.code(),
+            "message": message,
+        }));
+        (status, body).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use axum::body::to_bytes;
+    use serde_json::Value;
+    use sqlx::error::{DatabaseError, ErrorKind};
+
+    use super::*;
+
+    /// 带 SQLSTATE 的数据库错误
+    #[derive(Debug)]
+    struct PgError {
+        code: &'static str,
+        message: &'static str,
+    }
+
+    impl Display for PgError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.message)
+        }
+    }
+
+    impl std::error::Error for PgError {}
+
+    impl DatabaseError for PgError {
+        fn message(&self) -> &str {
+            self.message
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    fn database_error(code: &'static str, message: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(PgError { code, message }))
+    }
+
+    async fn body(err: AppError) -> (StatusCode, Value) {
+        let response = err.into_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn maps_sqlstate_to_status_and_code() {
+        let cases = [
+            (
+                "23505",
+                StatusCode::CONFLICT,
+                "unique_violation",
+                "duplicate key value violates unique constraint \"account_username_key\"",
+            ),
+            (
+                "23503",
+                StatusCode::NOT_FOUND,
+                "foreign_key_violation",
+                "insert or update on table \"uploads\" violates foreign key constraint",
+            ),
+            (
+                "23514",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "check_violation",
+                "new row for relation \"account\" violates check constraint",
+            ),
+        ];
+        for (sqlstate, status, code, message) in cases {
+            let (actual_status, body) = body(database_error(sqlstate, message).into()).await;
+            assert_eq!(actual_status, status, "{}", sqlstate);
+            assert_eq!(body["status"], "fail");
+            assert_eq!(body["code"], code);
+            assert_eq!(body["message"], message);
+        }
+    }
+
+    #[tokio::test]
+    async fn hides_other_database_errors() {
+        let errors = [
+            database_error(
+                "40001",
+                "could not serialize access due to concurrent update",
+            ),
+            database_error("42P01", "relation \"secret_table\" does not exist"),
+            sqlx::Error::PoolTimedOut,
+        ];
+        for err in errors {
+            let (status, body) = body(err.into()).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(body["status"], "error");
+            assert_eq!(body["code"], "database_error");
+            assert_eq!(body["message"], "Internal Server Error");
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_row_is_not_found() {
+        let (status, body) = body(sqlx::Error::RowNotFound.into()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "not_found");
+    }
+}
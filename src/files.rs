@@ -0,0 +1,618 @@
+use std::{path::Path as FsPath, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, Path, Query, State},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{FromRow, Pool, Postgres, QueryBuilder};
+
+use crate::authz::{Principal, RequireScope, Scope};
+use crate::blob_store::{BlobError, BlobStore};
+use crate::error::AppError;
+use crate::upload::{self, StagedFile, StoredFile, UploadError};
+use crate::users;
+
+/// 默认每页数量
+const DEFAULT_PAGE_SIZE: i64 = 20;
+/// 每页最大数量
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// 查看文件的权限
+pub struct FilesRead;
+
+impl Scope for FilesRead {
+    const NAME: &'static str = "files:read";
+}
+
+/// 上传和删除文件的权限
+pub struct FilesWrite;
+
+impl Scope for FilesWrite {
+    const NAME: &'static str = "files:write";
+}
+
+/// 文件目录的状态
+#[derive(Clone)]
+pub struct FilesState {
+    pub db: Pool<Postgres>,
+    pub store: Arc<dyn BlobStore>,
+}
+
+impl FromRef<FilesState> for Pool<Postgres> {
+    fn from_ref(state: &FilesState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// 文件目录中的一条记录
+///
+/// 内容相同的文件只保存一份，多条记录可以指向同一个 `storage_key`。
+#[derive(Serialize, Debug, FromRow)]
+pub struct FileRecord {
+    pub id: i32,
+    /// 上传时的文件名，已清理
+    pub filename: String,
+    /// 存储后端中的 key，只在服务端使用，不返回给客户端
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    /// 按文件内容识别出的类型
+    pub content_type: String,
+    pub size: i64,
+    /// 内容的 SHA-256，十六进制
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 文件列表的查询参数
+#[derive(Deserialize)]
+pub struct ListFiles {
+    /// 按文件名搜索，不区分大小写
+    pub q: Option<String>,
+    /// 游标，上一页最后一条记录的 id
+    pub after: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+/// 当前用户的 id，从已认证的身份中查找，需要放在认证中间件之后
+pub struct Owner(pub i32);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Owner
+where
+    Pool<Postgres>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = Principal::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let db = Pool::<Postgres>::from_ref(state);
+        match users::find_by_subject(&db, &principal.subject).await {
+            Ok(Some(user)) => Ok(Owner(user.id)),
+            Ok(None) => Err(AppError::Unauthorized("用户不存在".to_string()).into_response()),
+            Err(err) => Err(err.into_response()),
+        }
+    }
+}
+
+// 记录存在而对象读取失败，说明存储和目录不一致
+impl From<BlobError> for AppError {
+    fn from(err: BlobError) -> Self {
+        AppError::Internal(err.to_string())
+    }
+}
+
+/// 文件目录路由，需要已认证的身份：`GET /`、`GET /:id`、`DELETE /:id`、`GET /:id/content`
+pub fn router() -> Router<FilesState> {
+    Router::new()
+        .route("/", get(list_handler))
+        .route("/:id", get(find_handler).delete(delete_handler))
+        .route("/:id/content", get(content_handler))
+}
+
+/// 把接收到的文件保存到存储后端并登记到目录
+///
+/// 任何一个文件失败时，本次请求已经登记的记录都会被删除。
+pub async fn save_all(
+    db: &Pool<Postgres>,
+    store: &dyn BlobStore,
+    user_id: i32,
+    staged: Vec<StagedFile>,
+) -> Result<Vec<FileRecord>, UploadError> {
+    let mut saved: Vec<FileRecord> = vec![];
+    for file in &staged {
+        match save(db, store, user_id, file.path(), &file.file).await {
+            Ok(record) => saved.push(record),
+            Err(err) => {
+                for record in saved {
+                    if let Err(err) = delete(db, store, user_id, record.id).await {
+                        tracing::warn!("failed to delete file {}: {}", record.id, err);
+                    }
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(saved)
+}
+
+/// 保存单个文件并登记到目录
+///
+/// 已有相同内容（SHA-256 和大小都相同）的文件时不再保存新对象，新记录指向已有的对象，
+/// 本地文件留给调用方删除。
+pub async fn save(
+    db: &Pool<Postgres>,
+    store: &dyn BlobStore,
+    user_id: i32,
+    path: &FsPath,
+    file: &StoredFile,
+) -> Result<FileRecord, UploadError> {
+    // FOR SHARE 锁住被引用的记录，避免对象在登记完成前被删除最后一条记录的请求清理掉
+    let duplicate = sqlx::query_as::<_, FileRecord>(
+        "INSERT INTO uploads (user_id, filename, storage_key, content_type, size, sha256) \
+         SELECT $1, $2, storage_key, content_type, size, sha256 FROM ( \
+             SELECT storage_key, content_type, size, sha256 FROM uploads \
+             WHERE sha256 = $3 AND size = $4 LIMIT 1 FOR SHARE \
+         ) AS existing \
+         RETURNING id, filename, storage_key, content_type, size, sha256, created_at",
+    )
+    .bind(user_id)
+    .bind(&file.filename)
+    .bind(&file.sha256)
+    .bind(file.size as i64)
+    .fetch_optional(db)
+    .await?;
+    if let Some(record) = duplicate {
+        return Ok(record);
+    }
+
+    upload::store_file(store, path, file).await?;
+    let result = sqlx::query_as::<_, FileRecord>(
+        "INSERT INTO uploads (user_id, filename, storage_key, content_type, size, sha256) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         RETURNING id, filename, storage_key, content_type, size, sha256, created_at",
+    )
+    .bind(user_id)
+    .bind(&file.filename)
+    .bind(&file.key)
+    .bind(&file.content_type)
+    .bind(file.size as i64)
+    .bind(&file.sha256)
+    .fetch_one(db)
+    .await;
+    match result {
+        Ok(record) => Ok(record),
+        Err(err) => {
+            upload::delete(store, &file.key).await;
+            Err(err.into())
+        }
+    }
+}
+
+/// 用户的文件，按上传时间倒序，返回本页记录和下一页的游标
+pub async fn list(
+    db: &Pool<Postgres>,
+    user_id: i32,
+    params: &ListFiles,
+) -> Result<(Vec<FileRecord>, Option<i32>), AppError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT id, filename, storage_key, content_type, size, sha256, created_at FROM uploads \
+         WHERE user_id = ",
+    );
+    query.push_bind(user_id);
+    if let Some(q) = params.q.as_deref().filter(|q| !q.is_empty()) {
+        let q = q
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        query
+            .push(" AND filename ILIKE ")
+            .push_bind(format!("%{}%", q));
+    }
+    if let Some(after) = params.after {
+        query.push(" AND id < ").push_bind(after);
+    }
+    // 多取一条，用来判断是否还有下一页
+    query.push(" ORDER BY id DESC LIMIT ").push_bind(limit + 1);
+
+    let mut files = query.build_query_as::<FileRecord>().fetch_all(db).await?;
+    let next_cursor = if files.len() as i64 > limit {
+        files.truncate(limit as usize);
+        files.last().map(|file| file.id)
+    } else {
+        None
+    };
+    Ok((files, next_cursor))
+}
+
+/// 用户的单个文件，不存在时返回 404
+pub async fn find(db: &Pool<Postgres>, user_id: i32, id: i32) -> Result<FileRecord, AppError> {
+    sqlx::query_as::<_, FileRecord>(
+        "SELECT id, filename, storage_key, content_type, size, sha256, created_at FROM uploads \
+         WHERE id = $1 AND user_id = $2",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or_else(|| AppError::NotFound("文件不存在".to_string()))
+}
+
+/// 删除用户的文件，不存在时返回 404
+///
+/// 没有其他记录引用同一个对象时，对象和元数据也一起删除；删除对象失败只记录日志。
+pub async fn delete(
+    db: &Pool<Postgres>,
+    store: &dyn BlobStore,
+    user_id: i32,
+    id: i32,
+) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+    let key: String = sqlx::query_scalar(
+        "DELETE FROM uploads WHERE id = $1 AND user_id = $2 RETURNING storage_key",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("文件不存在".to_string()))?;
+    let referenced: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM uploads WHERE storage_key = $1)")
+            .bind(&key)
+            .fetch_one(&mut *tx)
+            .await?;
+    tx.commit().await?;
+
+    // 提交后记录已经不可见，新的上传不会再引用这个对象
+    if !referenced {
+        upload::delete(store, &key).await;
+    }
+    Ok(())
+}
+
+async fn list_handler(
+    State(state): State<FilesState>,
+    _: RequireScope<FilesRead>,
+    Owner(user_id): Owner,
+    Query(params): Query<ListFiles>,
+) -> Result<impl IntoResponse, AppError> {
+    let (files, next_cursor) = list(&state.db, user_id, &params).await?;
+    Ok(Json(json!({
+        "status": "success",
+        "results": files.len(),
+        "data": files,
+        "next_cursor": next_cursor
+    })))
+}
+
+async fn find_handler(
+    State(state): State<FilesState>,
+    _: RequireScope<FilesRead>,
+    Owner(user_id): Owner,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = find(&state.db, user_id, id).await?;
+    Ok(Json(json!({"status": "success", "data": file})))
+}
+
+async fn content_handler(
+    State(state): State<FilesState>,
+    _: RequireScope<FilesRead>,
+    Owner(user_id): Owner,
+    Path(id): Path<i32>,
+) -> Result<Response, AppError> {
+    let file = find(&state.db, user_id, id).await?;
+    let response = upload::send(
+        state.store.as_ref(),
+        &file.storage_key,
+        &file.filename,
+        &file.content_type,
+    )
+    .await?;
+    Ok(response)
+}
+
+async fn delete_handler(
+    State(state): State<FilesState>,
+    _: RequireScope<FilesWrite>,
+    Owner(user_id): Owner,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    delete(&state.db, state.store.as_ref(), user_id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::blob_store::LocalBlobStore;
+
+    struct Fixture {
+        dir: TempDir,
+        store: LocalBlobStore,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let store = LocalBlobStore::new(dir.path());
+            std::fs::create_dir(dir.path().join(".tmp")).unwrap();
+            Self { dir, store }
+        }
+
+        // 模拟接收完成、等待保存的文件
+        fn stage(&self, filename: &str, content: &[u8]) -> (PathBuf, StoredFile) {
+            let key = Uuid::new_v4().simple().to_string();
+            let path = self.dir.path().join(".tmp").join(format!("{}.part", key));
+            std::fs::write(&path, content).unwrap();
+            let file = StoredFile {
+                key,
+                filename: filename.to_string(),
+                content_type: "text/plain".to_string(),
+                size: content.len() as u64,
+                sha256: hex::encode(Sha256::digest(content)),
+                uploaded_at: Utc::now(),
+            };
+            (path, file)
+        }
+
+        async fn save(
+            &self,
+            db: &Pool<Postgres>,
+            user_id: i32,
+            filename: &str,
+            content: &[u8],
+        ) -> FileRecord {
+            let (path, file) = self.stage(filename, content);
+            save(db, &self.store, user_id, &path, &file).await.unwrap()
+        }
+
+        fn blob_exists(&self, key: &str) -> bool {
+            self.dir.path().join(key).exists()
+        }
+
+        fn meta_exists(&self, key: &str) -> bool {
+            self.dir.path().join(format!("{}.json", key)).exists()
+        }
+    }
+
+    async fn insert_user(db: &Pool<Postgres>, username: &str) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, 'x') RETURNING id",
+        )
+        .bind(username)
+        .bind(format!("{username}@example.com"))
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    // 直接登记，不保存对象，用于只测试查询的用例
+    async fn insert_record(db: &Pool<Postgres>, user_id: i32, filename: &str) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO uploads (user_id, filename, storage_key, content_type, size, sha256) \
+             VALUES ($1, $2, $3, 'text/plain', 1, $4) RETURNING id",
+        )
+        .bind(user_id)
+        .bind(filename)
+        .bind(Uuid::new_v4().simple().to_string())
+        .bind("0".repeat(64))
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    fn params(q: Option<&str>, after: Option<i32>, limit: Option<i64>) -> ListFiles {
+        ListFiles {
+            q: q.map(str::to_string),
+            after,
+            limit,
+        }
+    }
+
+    #[sqlx::test]
+    async fn identical_content_shares_one_object(db: Pool<Postgres>) {
+        let fixture = Fixture::new();
+        let alice = insert_user(&db, "alice").await;
+        let bob = insert_user(&db, "bob").await;
+
+        let first = fixture.save(&db, alice, "a.txt", b"hello").await;
+        assert!(fixture.blob_exists(&first.storage_key));
+        assert!(fixture.meta_exists(&first.storage_key));
+
+        let (path, file) = fixture.stage("b.txt", b"hello");
+        let second = save(&db, &fixture.store, alice, &path, &file)
+            .await
+            .unwrap();
+        let third = fixture.save(&db, bob, "c.txt", b"hello").await;
+        assert_eq!(second.storage_key, first.storage_key);
+        assert_eq!(third.storage_key, first.storage_key);
+        assert_eq!(second.filename, "b.txt");
+        // 重复的内容没有保存新对象，本地文件留给调用方删除
+        assert!(!fixture.blob_exists(&file.key));
+        assert!(path.exists());
+
+        let other = fixture.save(&db, alice, "d.txt", b"world").await;
+        assert_ne!(other.storage_key, first.storage_key);
+    }
+
+    #[sqlx::test]
+    async fn object_is_deleted_with_the_last_record(db: Pool<Postgres>) {
+        let fixture = Fixture::new();
+        let alice = insert_user(&db, "alice").await;
+        let bob = insert_user(&db, "bob").await;
+        let first = fixture.save(&db, alice, "a.txt", b"hello").await;
+        let second = fixture.save(&db, bob, "b.txt", b"hello").await;
+        let key = first.storage_key.clone();
+
+        delete(&db, &fixture.store, alice, first.id).await.unwrap();
+        assert!(fixture.blob_exists(&key));
+        assert!(fixture.meta_exists(&key));
+        // 另一个用户仍然可以读取
+        let record = find(&db, bob, second.id).await.unwrap();
+        assert_eq!(
+            fixture.store.get_bytes(&record.storage_key).await.unwrap(),
+            "hello"
+        );
+
+        delete(&db, &fixture.store, bob, second.id).await.unwrap();
+        assert!(!fixture.blob_exists(&key));
+        assert!(!fixture.meta_exists(&key));
+    }
+
+    #[sqlx::test]
+    async fn files_of_other_users_are_not_found(db: Pool<Postgres>) {
+        let fixture = Fixture::new();
+        let alice = insert_user(&db, "alice").await;
+        let bob = insert_user(&db, "bob").await;
+        let record = fixture.save(&db, alice, "a.txt", b"hello").await;
+
+        assert!(matches!(
+            find(&db, bob, record.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            delete(&db, &fixture.store, bob, record.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(find(&db, alice, record.id).await.is_ok());
+        assert!(fixture.blob_exists(&record.storage_key));
+
+        assert!(matches!(
+            delete(&db, &fixture.store, alice, record.id + 1).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn failed_batch_removes_saved_records(db: Pool<Postgres>) {
+        let fixture = Fixture::new();
+        let alice = insert_user(&db, "alice").await;
+        let (path, file) = fixture.stage("a.txt", b"hello");
+        let first = StagedFile::new(path, file);
+        // 第二个文件的本地文件不存在，保存失败
+        let (path, file) = fixture.stage("b.txt", b"world");
+        std::fs::remove_file(&path).unwrap();
+        let second = StagedFile::new(path, file);
+
+        let result = save_all(&db, &fixture.store, alice, vec![first, second]).await;
+        assert!(result.is_err());
+        let (files, _) = list(&db, alice, &params(None, None, None)).await.unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn lists_pages_newest_first(db: Pool<Postgres>) {
+        let alice = insert_user(&db, "alice").await;
+        let bob = insert_user(&db, "bob").await;
+        let mut ids = vec![];
+        for i in 0..5 {
+            ids.push(insert_record(&db, alice, &format!("{i}.txt")).await);
+        }
+        insert_record(&db, bob, "bob.txt").await;
+        ids.reverse();
+
+        let mut seen = vec![];
+        let mut after = None;
+        loop {
+            let (files, next) = list(&db, alice, &params(None, after, Some(2)))
+                .await
+                .unwrap();
+            assert!(files.len() <= 2);
+            seen.extend(files.iter().map(|file| file.id));
+            match next {
+                Some(cursor) => {
+                    assert_eq!(Some(cursor), files.last().map(|file| file.id));
+                    after = Some(cursor);
+                }
+                None => break,
+            }
+        }
+        assert_eq!(seen, ids);
+
+        // 正好取完时没有下一页
+        let (files, next) = list(&db, alice, &params(None, None, Some(5)))
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 5);
+        assert_eq!(next, None);
+    }
+
+    #[sqlx::test]
+    async fn search_escapes_wildcards(db: Pool<Postgres>) {
+        let alice = insert_user(&db, "alice").await;
+        for filename in [
+            "100%.txt",
+            "a_b.txt",
+            "axb.txt",
+            "Plain.TXT",
+            r"back\slash.txt",
+        ] {
+            insert_record(&db, alice, filename).await;
+        }
+
+        let search = |q: &'static str| {
+            let db = db.clone();
+            async move {
+                let (files, _) = list(&db, alice, &params(Some(q), None, None))
+                    .await
+                    .unwrap();
+                let mut names: Vec<String> = files.into_iter().map(|file| file.filename).collect();
+                names.sort();
+                names
+            }
+        };
+        assert_eq!(search("%").await, ["100%.txt"]);
+        assert_eq!(search("_").await, ["a_b.txt"]);
+        assert_eq!(search("A_B").await, ["a_b.txt"]);
+        assert_eq!(search(r"\").await, [r"back\slash.txt"]);
+        assert_eq!(search("plain").await, ["Plain.TXT"]);
+        // 空字符串不过滤
+        assert_eq!(search("").await.len(), 5);
+    }
+
+    #[sqlx::test]
+    async fn page_size_is_clamped(db: Pool<Postgres>) {
+        let alice = insert_user(&db, "alice").await;
+        for i in 0..=MAX_PAGE_SIZE {
+            insert_record(&db, alice, &format!("{i}.txt")).await;
+        }
+
+        let (files, next) = list(&db, alice, &params(None, None, Some(1000)))
+            .await
+            .unwrap();
+        assert_eq!(files.len() as i64, MAX_PAGE_SIZE);
+        assert!(next.is_some());
+
+        let (files, _) = list(&db, alice, &params(None, None, None)).await.unwrap();
+        assert_eq!(files.len() as i64, DEFAULT_PAGE_SIZE);
+
+        for limit in [0, -5] {
+            let (files, next) = list(&db, alice, &params(None, None, Some(limit)))
+                .await
+                .unwrap();
+            assert_eq!(files.len(), 1);
+            assert_eq!(next, files.last().map(|file| file.id));
+        }
+    }
+}
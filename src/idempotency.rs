@@ -0,0 +1,458 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use http_body_util::LengthLimitError;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres, Row};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// 客户端重试时携带的幂等键
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// 标记本次响应是重放的
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+/// 幂等键的最大长度
+const MAX_KEY_LENGTH: usize = 255;
+/// 幂等键保留的时长，过期后同一个 key 可以重新使用
+const KEY_TTL: &str = "24 hours";
+/// 处理中的请求占用 key 的最长秒数；超过后认为处理它的进程已经退出，key 可以被重新占用
+const IN_FLIGHT_LEASE_SECS: i64 = 60;
+/// 缓存的请求体和响应体的最大字节数，与 axum 默认的请求体限制相同
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+// 计算请求指纹：方法 + 路径 + 请求体
+fn fingerprint(method: &str, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// 幂等中间件
+///
+/// 请求带有 `Idempotency-Key` 头时，第一次的响应会被保存到 Postgres，
+/// 之后相同 key、相同请求的重试直接重放保存的响应；
+/// 相同 key 但请求不同时返回 422。没有该头的请求原样放行。
+pub async fn idempotency(State(db): State<Pool<Postgres>>, req: Request, next: Next) -> Response {
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(key) if !key.trim().is_empty() && key.len() <= MAX_KEY_LENGTH => key.to_string(),
+            _ => {
+                return AppError::BadRequest(format!(
+                    "Idempotency-Key 必须是 1-{} 个可见字符",
+                    MAX_KEY_LENGTH
+                ))
+                .into_response()
+            }
+        },
+        None => return next.run(req).await,
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(err) if is_length_limit(&err) => {
+            return AppError::PayloadTooLarge(format!("请求体不能超过 {} 字节", MAX_BODY_SIZE))
+                .into_response()
+        }
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    let request_hash = fingerprint(parts.method.as_str(), path, &body);
+
+    let lease_id = Uuid::new_v4().simple().to_string();
+    let claimed = match claim(&db, &key, &request_hash, &lease_id).await {
+        Ok(claimed) => claimed,
+        Err(err) => return AppError::from(err).into_response(),
+    };
+
+    if !claimed {
+        return replay(&db, &key, &request_hash).await;
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // 服务端错误不保存，释放 key 让客户端可以重试
+    if response.status().is_server_error() {
+        release(&db, &key, &lease_id).await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!("failed to buffer response for {}: {:?}", key, err);
+            release(&db, &key, &lease_id).await;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let stored = StoredResponse {
+        status_code: parts.status.as_u16() as i16,
+        content_type,
+        body: body.as_ref(),
+    };
+    match store(&db, &key, &lease_id, stored).await {
+        Ok(true) => {}
+        Ok(false) => tracing::warn!("lease on idempotency key {} was taken over", key),
+        Err(err) => tracing::error!("failed to store idempotent response {}: {:?}", key, err),
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+// 抢占 key 并记下租约 ID；已过期的 key 和租约到期仍未完成的 key 会被重新占用
+async fn claim(
+    db: &Pool<Postgres>,
+    key: &str,
+    request_hash: &str,
+    lease_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(&format!(
+        "INSERT INTO idempotency_keys (key, request_hash, lease_id) VALUES ($1, $2, $3) \
+         ON CONFLICT (key) DO UPDATE \
+         SET request_hash = EXCLUDED.request_hash, lease_id = EXCLUDED.lease_id, \
+             status_code = NULL, content_type = NULL, response_body = NULL, created_at = now() \
+         WHERE idempotency_keys.created_at < now() - interval '{}' \
+            OR (idempotency_keys.status_code IS NULL \
+                AND idempotency_keys.created_at < now() - interval '{} seconds')",
+        KEY_TTL, IN_FLIGHT_LEASE_SECS
+    ))
+    .bind(key)
+    .bind(request_hash)
+    .bind(lease_id)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// 要保存的响应
+struct StoredResponse<'a> {
+    status_code: i16,
+    content_type: Option<&'a str>,
+    body: &'a [u8],
+}
+
+// 保存响应；租约已被其它请求接管时不写入，返回 `false`
+async fn store(
+    db: &Pool<Postgres>,
+    key: &str,
+    lease_id: &str,
+    response: StoredResponse<'_>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE idempotency_keys SET status_code = $1, content_type = $2, response_body = $3 \
+         WHERE key = $4 AND lease_id = $5 AND status_code IS NULL",
+    )
+    .bind(response.status_code)
+    .bind(response.content_type)
+    .bind(response.body)
+    .bind(key)
+    .bind(lease_id)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+// 释放 key，只释放自己仍持有的租约；失败只记录日志，key 会在租约到期后被重新占用
+async fn release(db: &Pool<Postgres>, key: &str, lease_id: &str) {
+    if let Err(err) = sqlx::query(
+        "DELETE FROM idempotency_keys WHERE key = $1 AND lease_id = $2 AND status_code IS NULL",
+    )
+    .bind(key)
+    .bind(lease_id)
+    .execute(db)
+    .await
+    {
+        tracing::error!("failed to release idempotency key {}: {:?}", key, err);
+    }
+}
+
+// 请求体超过了 `to_bytes` 的限制
+fn is_length_limit(err: &axum::Error) -> bool {
+    std::error::Error::source(err).is_some_and(|source| source.is::<LengthLimitError>())
+}
+
+/// 已保存的幂等键
+struct StoredKey {
+    request_hash: String,
+    /// 为 `None` 表示请求还在处理中
+    status_code: Option<i16>,
+    content_type: Option<String>,
+    response_body: Option<Vec<u8>>,
+    created_at: DateTime<Utc>,
+}
+
+// 重放已保存的响应
+async fn replay(db: &Pool<Postgres>, key: &str, request_hash: &str) -> Response {
+    let row = sqlx::query(
+        "SELECT request_hash, status_code, content_type, response_body, created_at \
+         FROM idempotency_keys WHERE key = $1",
+    )
+    .bind(key)
+    .fetch_optional(db)
+    .await;
+    let row = match row {
+        Ok(Some(row)) => row,
+        // 刚好被释放，让客户端稍后重试
+        Ok(None) => {
+            return AppError::Conflict(format!("Idempotency-Key {} 对应的请求正在处理中", key))
+                .into_response()
+        }
+        Err(err) => return AppError::from(err).into_response(),
+    };
+    let stored = StoredKey {
+        request_hash: row.get("request_hash"),
+        status_code: row.get("status_code"),
+        content_type: row.get("content_type"),
+        response_body: row.get("response_body"),
+        created_at: row.get("created_at"),
+    };
+    replay_stored(key, request_hash, stored, Utc::now())
+}
+
+// 按已保存的 key 生成响应：请求不同返回 422，仍在处理中返回 409，否则重放保存的响应
+fn replay_stored(key: &str, request_hash: &str, stored: StoredKey, now: DateTime<Utc>) -> Response {
+    if stored.request_hash != request_hash {
+        return AppError::UnprocessableEntity(format!(
+            "Idempotency-Key {} 已被用于另一个不同的请求",
+            key
+        ))
+        .into_response();
+    }
+
+    let Some(status_code) = stored.status_code else {
+        // 告诉客户端租约最晚何时到期，到期后重试可以重新占用 key
+        let retry_after = lease_remaining(stored.created_at, now).max(1);
+        let mut response =
+            AppError::Conflict(format!("Idempotency-Key {} 对应的请求正在处理中", key))
+                .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        return response;
+    };
+
+    let body = stored.response_body.unwrap_or_default();
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = StatusCode::from_u16(status_code as u16).unwrap_or(StatusCode::OK);
+    if let Some(content_type) = stored
+        .content_type
+        .and_then(|v| HeaderValue::from_str(&v).ok())
+    {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+        .headers_mut()
+        .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+// 处理中的请求的租约还剩多少秒，已到期时为 0
+fn lease_remaining(created_at: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    let elapsed = (now - created_at).num_seconds();
+    (IN_FLIGHT_LEASE_SECS - elapsed).max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::to_bytes, middleware, routing::post, Router};
+    use chrono::Duration;
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn stored(request_hash: &str, status_code: Option<i16>, age_secs: i64) -> StoredKey {
+        StoredKey {
+            request_hash: request_hash.to_string(),
+            status_code,
+            content_type: Some("application/json".to_string()),
+            response_body: Some(br#"{"id":1}"#.to_vec()),
+            created_at: Utc::now() - Duration::seconds(age_secs),
+        }
+    }
+
+    // 数据库不可用：这些请求在访问数据库之前就应该被处理掉
+    fn app() -> Router {
+        let db = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        Router::new()
+            .route("/transfers", post(|| async { "handled" }))
+            .layer(middleware::from_fn_with_state(db, idempotency))
+    }
+
+    fn request(key: Option<&str>, body: Vec<u8>) -> Request {
+        let mut builder = Request::post("/transfers");
+        if let Some(key) = key {
+            builder = builder.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        builder.body(Body::from(body)).unwrap()
+    }
+
+    #[test]
+    fn fingerprint_covers_method_path_and_body() {
+        let hash = fingerprint("POST", "/transfers", b"{}");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, fingerprint("POST", "/transfers", b"{}"));
+        assert_ne!(hash, fingerprint("PUT", "/transfers", b"{}"));
+        assert_ne!(hash, fingerprint("POST", "/transfers?dry_run=1", b"{}"));
+        assert_ne!(hash, fingerprint("POST", "/transfers", b"{ }"));
+        // 分隔符避免字段拼接后相同
+        assert_ne!(
+            fingerprint("POST", "/a", b"b"),
+            fingerprint("POST", "/ab", b"")
+        );
+    }
+
+    #[test]
+    fn lease_expires_after_in_flight_lease() {
+        let now = Utc::now();
+        assert_eq!(lease_remaining(now, now), IN_FLIGHT_LEASE_SECS);
+        assert_eq!(lease_remaining(now - Duration::seconds(45), now), 15);
+        assert_eq!(
+            lease_remaining(now - Duration::seconds(IN_FLIGHT_LEASE_SECS), now),
+            0
+        );
+        assert_eq!(lease_remaining(now - Duration::hours(1), now), 0);
+    }
+
+    #[tokio::test]
+    async fn replays_completed_response() {
+        let response = replay_stored("k1", "h1", stored("h1", Some(201), 5), Utc::now());
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), br#"{"id":1}"#);
+    }
+
+    #[test]
+    fn rejects_reused_key_for_different_request() {
+        let response = replay_stored("k1", "h2", stored("h1", Some(201), 5), Utc::now());
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(response.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+
+        // 请求不同时即使还在处理中也返回 422
+        let response = replay_stored("k1", "h2", stored("h1", None, 5), Utc::now());
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn in_flight_key_is_a_conflict_until_lease_expires() {
+        let response = replay_stored("k1", "h1", stored("h1", None, 45), Utc::now());
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "15");
+
+        // 租约已到期但还没被重新占用，让客户端立即重试
+        let response = replay_stored("k1", "h1", stored("h1", None, 300), Utc::now());
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[tokio::test]
+    async fn passes_through_requests_without_key() {
+        let response = app().oneshot(request(None, b"{}".to_vec())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), b"handled");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_keys() {
+        let long_key = "k".repeat(MAX_KEY_LENGTH + 1);
+        for key in ["", "   ", long_key.as_str()] {
+            let response = app().oneshot(request(Some(key), Vec::new())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{:?}", key);
+        }
+    }
+
+    fn response(body: &[u8]) -> StoredResponse<'_> {
+        StoredResponse {
+            status_code: 201,
+            content_type: Some("application/json"),
+            body,
+        }
+    }
+
+    // 把 key 的占用时间提前，模拟租约到期
+    async fn expire_lease(db: &Pool<Postgres>, key: &str) {
+        sqlx::query(
+            "UPDATE idempotency_keys SET created_at = now() - interval '2 minutes' WHERE key = $1",
+        )
+        .bind(key)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn in_flight_key_cannot_be_claimed_twice(db: Pool<Postgres>) {
+        assert!(claim(&db, "k1", "h1", "lease-a").await.unwrap());
+        assert!(!claim(&db, "k1", "h1", "lease-b").await.unwrap());
+
+        // 完成后在保留期内也不能重新占用
+        assert!(store(&db, "k1", "lease-a", response(b"{}")).await.unwrap());
+        expire_lease(&db, "k1").await;
+        assert!(!claim(&db, "k1", "h1", "lease-b").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn expired_lease_holder_cannot_store_or_release(db: Pool<Postgres>) {
+        assert!(claim(&db, "k1", "h1", "lease-a").await.unwrap());
+        expire_lease(&db, "k1").await;
+        assert!(claim(&db, "k1", "h1", "lease-b").await.unwrap());
+
+        // 旧请求的写入和释放都被忽略
+        assert!(!store(&db, "k1", "lease-a", response(b"old")).await.unwrap());
+        release(&db, "k1", "lease-a").await;
+        assert!(store(&db, "k1", "lease-b", response(b"new")).await.unwrap());
+
+        // 响应只能写入一次
+        assert!(!store(&db, "k1", "lease-b", response(b"again"))
+            .await
+            .unwrap());
+        let body: Vec<u8> =
+            sqlx::query_scalar("SELECT response_body FROM idempotency_keys WHERE key = $1")
+                .bind("k1")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(body, b"new");
+    }
+
+    #[sqlx::test]
+    async fn release_frees_own_lease(db: Pool<Postgres>) {
+        assert!(claim(&db, "k1", "h1", "lease-a").await.unwrap());
+        release(&db, "k1", "lease-a").await;
+        assert!(claim(&db, "k1", "h2", "lease-b").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_over_limit() {
+        let response = app()
+            .oneshot(request(Some("k1"), vec![b'x'; MAX_BODY_SIZE + 1]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
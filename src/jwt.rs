@@ -0,0 +1,1153 @@
+use std::{fmt::Display, sync::OnceLock};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use jsonwebtoken::{
+    errors::{Error as JwtError, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType,
+        PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use simple_asn1::ASN1Block;
+use uuid::Uuid;
+
+use crate::authz::Principal;
+use crate::config::{AppConfig, JwtConfig, JwtKeyConfig};
+use crate::redis_client::{RedisPool, RedisStoreError};
+
+/// Redis 中刷新令牌的 key 前缀
+const REFRESH_KEY_PREFIX: &str = "axum_rs_refresh:";
+/// Redis 中已使用的刷新令牌的 key 前缀
+const REFRESH_USED_KEY_PREFIX: &str = "axum_rs_refresh_used:";
+/// Redis 中刷新令牌家族的 key 前缀
+const FAMILY_KEY_PREFIX: &str = "axum_rs_refresh_family:";
+/// Redis 中已吊销的访问令牌 `jti` 的 key 前缀
+const DENYLIST_KEY_PREFIX: &str = "axum_rs_jwt_denylist:";
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+
+/// 一个签名密钥
+struct Key {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    /// 只有用于签发的密钥有私钥
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    /// 公开的公钥，对称密钥没有
+    jwk: Option<Jwk>,
+}
+
+/// JWT 签名密钥
+///
+/// 配置了 `jwt.keys` 时使用非对称密钥（RS256、ES256、EdDSA）：第一个密钥签发，
+/// 所有密钥都可以校验，轮换期间旧密钥签发的令牌仍然有效；公钥通过 JWKS 公开。
+/// 没有配置时使用 `jwt.secret` 以 HS256 签名。
+pub struct Keys {
+    keys: Vec<Key>,
+}
+
+impl Keys {
+    /// 按全局配置加载，密钥文件无效时退出
+    pub fn global() -> &'static Keys {
+        KEYS.get_or_init(|| match Self::from_config(&AppConfig::global().jwt) {
+            Ok(keys) => keys,
+            Err(err) => {
+                println!("🔥 Failed to load JWT keys: {}", err);
+                std::process::exit(1);
+            }
+        })
+    }
+
+    pub fn from_config(config: &JwtConfig) -> Result<Self, String> {
+        if config.keys.is_empty() {
+            return Ok(Self::from_secret(config.secret.as_bytes()));
+        }
+        let keys = config
+            .keys
+            .iter()
+            .map(|key| load_key(key).map_err(|err| format!("{}: {}", key.kid, err)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { keys })
+    }
+
+    /// 使用 HS256 对称密钥
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            keys: vec![Key {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                encoding: Some(EncodingKey::from_secret(secret)),
+                decoding: DecodingKey::from_secret(secret),
+                jwk: None,
+            }],
+        }
+    }
+
+    /// 用当前密钥签发，头部带上 `kid`
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+        let key = &self.keys[0];
+        let encoding = key
+            .encoding
+            .as_ref()
+            .ok_or_else(|| JwtError::from(ErrorKind::InvalidKeyFormat))?;
+        let mut header = Header::new(key.algorithm);
+        header.kid = key.kid.clone();
+        jsonwebtoken::encode(&header, claims, encoding)
+    }
+
+    /// 校验并解码
+    ///
+    /// 依次尝试算法相同的密钥，`kid` 匹配的优先；`validation` 中的算法会被忽略。
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        validation: &Validation,
+    ) -> Result<TokenData<T>, JwtError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let mut result = Err(JwtError::from(ErrorKind::InvalidAlgorithm));
+        for key in self.candidates(&header) {
+            let mut validation = validation.clone();
+            validation.algorithms = vec![key.algorithm];
+            result = jsonwebtoken::decode(token, &key.decoding, &validation);
+            // 签名不匹配时换下一个密钥，其它错误与密钥无关
+            match &result {
+                Err(err) if *err.kind() == ErrorKind::InvalidSignature => continue,
+                _ => break,
+            }
+        }
+        result
+    }
+
+    // 算法与令牌相同的密钥，`kid` 匹配的排在前面
+    fn candidates(&self, header: &Header) -> Vec<&Key> {
+        let mut candidates: Vec<&Key> = self
+            .keys
+            .iter()
+            .filter(|key| key.algorithm == header.alg)
+            .collect();
+        candidates.sort_by_key(|key| header.kid.is_none() || key.kid != header.kid);
+        candidates
+    }
+
+    /// 所有公钥，供其它服务校验令牌
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+// 从 PEM 文件加载一个非对称密钥
+fn load_key(config: &JwtKeyConfig) -> Result<Key, String> {
+    let read = |path: &str| std::fs::read(path).map_err(|err| format!("{}: {}", path, err));
+    let public_pem = read(&config.public_key)?;
+    let private_pem = config.private_key.as_deref().map(read).transpose()?;
+
+    let (algorithm, key_algorithm) = match config.algorithm.as_str() {
+        "RS256" => (Algorithm::RS256, KeyAlgorithm::RS256),
+        "ES256" => (Algorithm::ES256, KeyAlgorithm::ES256),
+        "EdDSA" => (Algorithm::EdDSA, KeyAlgorithm::EdDSA),
+        other => return Err(format!("unsupported algorithm {}", other)),
+    };
+    let (encoding, decoding) = match algorithm {
+        Algorithm::RS256 => (
+            private_pem
+                .map(|pem| EncodingKey::from_rsa_pem(&pem))
+                .transpose(),
+            DecodingKey::from_rsa_pem(&public_pem),
+        ),
+        Algorithm::ES256 => (
+            private_pem
+                .map(|pem| EncodingKey::from_ec_pem(&pem))
+                .transpose(),
+            DecodingKey::from_ec_pem(&public_pem),
+        ),
+        _ => (
+            private_pem
+                .map(|pem| EncodingKey::from_ed_pem(&pem))
+                .transpose(),
+            DecodingKey::from_ed_pem(&public_pem),
+        ),
+    };
+    let encoding = encoding.map_err(|err| format!("invalid private key: {}", err))?;
+    let decoding = decoding.map_err(|err| format!("invalid public key: {}", err))?;
+
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(config.kid.clone()),
+            ..Default::default()
+        },
+        algorithm: public_key_parameters(algorithm, &public_pem)?,
+    };
+    Ok(Key {
+        kid: Some(config.kid.clone()),
+        algorithm,
+        encoding,
+        decoding,
+        jwk: Some(jwk),
+    })
+}
+
+// 从 PEM 编码的公钥中取出 JWK 需要的参数
+fn public_key_parameters(algorithm: Algorithm, pem: &[u8]) -> Result<AlgorithmParameters, String> {
+    let pem = pem::parse(pem).map_err(|err| format!("invalid public key: {}", err))?;
+    // PKCS#1 格式的 RSA 公钥
+    if pem.tag() == "RSA PUBLIC KEY" {
+        return rsa_parameters(pem.contents());
+    }
+    let key = subject_public_key(pem.contents())?;
+    match algorithm {
+        Algorithm::RS256 => rsa_parameters(&key),
+        Algorithm::ES256 => {
+            // 未压缩的点：0x04 || x || y
+            if key.len() != 65 || key[0] != 0x04 {
+                return Err("ES256 requires an uncompressed P-256 public key".to_string());
+            }
+            Ok(AlgorithmParameters::EllipticCurve(
+                EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve: EllipticCurve::P256,
+                    x: URL_SAFE_NO_PAD.encode(&key[1..33]),
+                    y: URL_SAFE_NO_PAD.encode(&key[33..]),
+                },
+            ))
+        }
+        _ => {
+            if key.len() != 32 {
+                return Err("EdDSA requires an Ed25519 public key".to_string());
+            }
+            Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(&key),
+            }))
+        }
+    }
+}
+
+// SubjectPublicKeyInfo 中 BIT STRING 里的公钥
+fn subject_public_key(der: &[u8]) -> Result<Vec<u8>, String> {
+    let blocks =
+        simple_asn1::from_der(der).map_err(|err| format!("invalid public key: {}", err))?;
+    match blocks.first() {
+        Some(ASN1Block::Sequence(_, items)) => items.iter().find_map(|item| match item {
+            ASN1Block::BitString(_, _, bits) => Some(bits.clone()),
+            _ => None,
+        }),
+        _ => None,
+    }
+    .ok_or_else(|| "public key must be PEM encoded SubjectPublicKeyInfo".to_string())
+}
+
+// PKCS#1 的 RSA 公钥：SEQUENCE { n, e }
+fn rsa_parameters(der: &[u8]) -> Result<AlgorithmParameters, String> {
+    let blocks =
+        simple_asn1::from_der(der).map_err(|err| format!("invalid RSA public key: {}", err))?;
+    let Some(ASN1Block::Sequence(_, items)) = blocks.first() else {
+        return Err("invalid RSA public key".to_string());
+    };
+    let [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] = items.as_slice() else {
+        return Err("invalid RSA public key".to_string());
+    };
+    Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: URL_SAFE_NO_PAD.encode(n.to_bytes_be().1),
+        e: URL_SAFE_NO_PAD.encode(e.to_bytes_be().1),
+    }))
+}
+
+/// 令牌相关的错误
+#[derive(Debug)]
+pub enum TokenError {
+    /// 刷新令牌不存在、已过期或所在家族已被吊销
+    Invalid,
+    /// 刷新令牌被重复使用，整个家族已被吊销
+    Reused,
+    /// Redis 出错
+    Redis(RedisStoreError),
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::Invalid => write!(f, "invalid refresh token"),
+            TokenError::Reused => write!(f, "refresh token reuse detected"),
+            TokenError::Redis(err) => write!(f, "token store error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+impl From<RedisStoreError> for TokenError {
+    fn from(err: RedisStoreError) -> Self {
+        TokenError::Redis(err)
+    }
+}
+
+/// 认证错误
+///
+/// 访问令牌的错误返回 401，并按 RFC 6750 带上 `WWW-Authenticate` 头。
+#[derive(Debug)]
+pub enum AuthError {
+    /// 用户名或密码错误
+    WrongCredentials,
+    /// 缺少用户名或密码
+    MissingCredentials,
+    /// 签发令牌失败
+    TokenCreation,
+    /// 请求中没有访问令牌
+    MissingToken,
+    /// 令牌格式错误或缺少必需的声明
+    MalformedToken,
+    /// 令牌已过期
+    ExpiredToken,
+    /// 令牌尚未生效（`nbf` 或 `iat` 在未来）
+    ImmatureToken,
+    /// 签名无效或算法不被接受
+    BadSignature,
+    /// `aud` 不匹配
+    WrongAudience,
+    /// `iss` 不匹配
+    WrongIssuer,
+    /// 令牌已被吊销
+    RevokedToken,
+    /// 刷新令牌无效
+    InvalidRefreshToken,
+    /// 刷新令牌被重复使用
+    RefreshTokenReused,
+    /// 服务端错误
+    Internal,
+}
+
+impl AuthError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::MissingCredentials | AuthError::InvalidRefreshToken => {
+                StatusCode::BAD_REQUEST
+            }
+            AuthError::TokenCreation | AuthError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    // `WWW-Authenticate` 中的 error_description，不是访问令牌的错误时为 `None`
+    fn token_error(&self) -> Option<&'static str> {
+        match self {
+            AuthError::MalformedToken => Some("The access token is malformed"),
+            AuthError::ExpiredToken => Some("The access token expired"),
+            AuthError::ImmatureToken => Some("The access token is not yet valid"),
+            AuthError::BadSignature => Some("The access token signature is invalid"),
+            AuthError::WrongAudience => Some("The access token audience is invalid"),
+            AuthError::WrongIssuer => Some("The access token issuer is invalid"),
+            AuthError::RevokedToken => Some("The access token has been revoked"),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            AuthError::WrongCredentials => "Wrong credentials",
+            AuthError::MissingCredentials => "Missing credentials",
+            AuthError::TokenCreation => "Token creation error",
+            AuthError::MissingToken => "Missing access token",
+            AuthError::InvalidRefreshToken => "Invalid refresh token",
+            AuthError::RefreshTokenReused => "Refresh token reused, please log in again",
+            AuthError::Internal => "Internal server error",
+            err => err.token_error().unwrap_or_default(),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<TokenError> for AuthError {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::Invalid => AuthError::InvalidRefreshToken,
+            TokenError::Reused => AuthError::RefreshTokenReused,
+            TokenError::Redis(err) => {
+                tracing::error!("{}", err);
+                AuthError::Internal
+            }
+        }
+    }
+}
+
+impl From<JwtError> for AuthError {
+    fn from(err: JwtError) -> Self {
+        match err.kind() {
+            ErrorKind::ExpiredSignature => AuthError::ExpiredToken,
+            ErrorKind::ImmatureSignature => AuthError::ImmatureToken,
+            ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => AuthError::BadSignature,
+            ErrorKind::InvalidAudience => AuthError::WrongAudience,
+            ErrorKind::InvalidIssuer => AuthError::WrongIssuer,
+            _ => AuthError::MalformedToken,
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let mut headers = HeaderMap::new();
+        let challenge = match (self.token_error(), &self) {
+            (Some(description), _) => Some(format!(
+                r#"Bearer error="invalid_token", error_description="{}""#,
+                description
+            )),
+            // 没有提供令牌时不带错误码
+            (None, AuthError::MissingToken) => Some("Bearer".to_string()),
+            _ => None,
+        };
+        if let Some(challenge) = challenge {
+            headers.insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_str(&challenge).unwrap(),
+            );
+        }
+        let body = Json(json!({
+            "error": self.to_string(),
+        }));
+        (self.status(), headers, body).into_response()
+    }
+}
+
+/// 访问令牌的声明
+///
+/// 除注册声明外的自定义声明（如角色、权限范围）放在 `T` 中，与注册声明平铺在同一层。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims<T> {
+    pub sub: String,
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
+    pub nbf: i64,
+    pub exp: i64,
+    /// 令牌 ID，用于吊销
+    pub jti: String,
+    #[serde(flatten)]
+    pub custom: T,
+}
+
+/// 访问令牌中与授权相关的声明，可以平铺到其它自定义声明中
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessClaims {
+    /// 角色
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// 权限范围，空格分隔
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl From<Claims<AccessClaims>> for Principal {
+    fn from(claims: Claims<AccessClaims>) -> Self {
+        Self {
+            subject: claims.sub,
+            roles: claims.custom.roles,
+            scopes: Some(
+                claims
+                    .custom
+                    .scope
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// 认证中间件：校验 `Authorization: Bearer` 中的访问令牌，把身份放入请求扩展
+///
+/// 没有 `Authorization` 头时直接放行，由需要身份的路由自己返回 401。
+pub async fn authenticate(
+    State(tokens): State<TokenStore>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    if request.headers().contains_key(header::AUTHORIZATION) {
+        let token = bearer_token(request.headers())?;
+        let claims: Claims<AccessClaims> = tokens.verify(token).await?;
+        request.extensions_mut().insert(Principal::from(claims));
+    }
+    Ok(next.run(request).await)
+}
+
+/// 从 `Authorization: Bearer` 中校验访问令牌
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Claims<T>
+where
+    T: DeserializeOwned + Send,
+    TokenStore: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)?;
+        TokenStore::from_ref(state).verify(token).await
+    }
+}
+
+/// 取出 `Authorization: Bearer` 中的令牌
+pub fn bearer_token(headers: &HeaderMap) -> Result<&str, AuthError> {
+    let value = headers
+        .get(header::AUTHORIZATION)
+        .ok_or(AuthError::MissingToken)?
+        .to_str()
+        .map_err(|_| AuthError::MalformedToken)?;
+    match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty() => {
+            Ok(token.trim())
+        }
+        _ => Err(AuthError::MalformedToken),
+    }
+}
+
+/// 刷新令牌在 Redis 中保存的信息
+#[derive(Serialize, Deserialize)]
+struct RefreshRecord {
+    family: String,
+    sub: String,
+    #[serde(default)]
+    scope: String,
+}
+
+/// 换发的刷新令牌
+#[derive(Debug)]
+pub struct RefreshGrant {
+    /// 令牌所属的用户
+    pub sub: String,
+    /// 登录时授予的权限范围
+    pub scope: String,
+    /// 新的刷新令牌
+    pub refresh_token: String,
+}
+
+/// 令牌存储
+///
+/// 刷新令牌是随机字符串，Redis 中只保存它的 SHA-256。每次刷新都会换发新的刷新令牌，
+/// 同一次登录换发的令牌属于同一个家族。已经用过的刷新令牌再次出现，说明它可能被盗，
+/// 此时吊销整个家族，持有者需要重新登录。
+///
+/// 访问令牌只在吊销时按 `jti` 记入黑名单，黑名单在令牌过期后自动清除。
+#[derive(Clone)]
+pub struct TokenStore {
+    redis: RedisPool,
+    access_ttl_secs: u64,
+    refresh_ttl_secs: u64,
+    issuer: String,
+    audience: String,
+    leeway_secs: u64,
+}
+
+impl TokenStore {
+    /// 按全局配置创建
+    pub fn from_config(redis: RedisPool) -> Self {
+        Self::new(redis, &AppConfig::global().jwt)
+    }
+
+    pub fn new(redis: RedisPool, config: &JwtConfig) -> Self {
+        Self {
+            redis,
+            access_ttl_secs: config.access_ttl_secs,
+            refresh_ttl_secs: config.refresh_ttl_secs,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            leeway_secs: config.leeway_secs,
+        }
+    }
+
+    /// 访问令牌的有效期（秒）
+    pub fn access_ttl_secs(&self) -> u64 {
+        self.access_ttl_secs
+    }
+
+    /// 为新的访问令牌生成声明，立即生效
+    pub fn access_claims<T>(&self, sub: String, custom: T) -> Claims<T> {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub,
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            iat: now,
+            nbf: now,
+            exp: now + self.access_ttl_secs as i64,
+            jti: Uuid::new_v4().to_string(),
+            custom,
+        }
+    }
+
+    /// 校验访问令牌：签名、`exp`、`nbf`、`iat`、`iss`、`aud`，以及是否已被吊销
+    pub async fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<Claims<T>, AuthError> {
+        let claims = decode_access(
+            Keys::global(),
+            token,
+            &self.issuer,
+            &self.audience,
+            self.leeway_secs,
+        )?;
+        // 已吊销的令牌即使没有过期也不能再使用
+        if self.is_revoked(&claims.jti).await? {
+            return Err(AuthError::RevokedToken);
+        }
+        Ok(claims)
+    }
+
+    /// 登录时签发刷新令牌，开启一个新的家族；`scope` 为本次登录授予的权限范围
+    pub async fn issue_refresh(&self, sub: &str, scope: &str) -> Result<String, TokenError> {
+        let family = Uuid::new_v4().to_string();
+        self.redis
+            .set_json(&family_key(&family), &sub, Some(self.refresh_ttl_secs))
+            .await?;
+        let record = RefreshRecord {
+            family,
+            sub: sub.to_string(),
+            scope: scope.to_string(),
+        };
+        self.new_refresh(&record).await
+    }
+
+    /// 用刷新令牌换发新的刷新令牌
+    ///
+    /// 旧令牌随即失效；再次使用旧令牌会吊销整个家族。
+    pub async fn rotate_refresh(&self, token: &str) -> Result<RefreshGrant, TokenError> {
+        let hash = hash_token(token);
+        let record: RefreshRecord = self
+            .redis
+            .get_json(&refresh_key(&hash))
+            .await?
+            .ok_or(TokenError::Invalid)?;
+        if !self.redis.exists(&family_key(&record.family)).await? {
+            return Err(TokenError::Invalid);
+        }
+
+        // 只有第一次使用能写入成功
+        let first_use = self
+            .redis
+            .set_nx(
+                &format!("{}{}", REFRESH_USED_KEY_PREFIX, hash),
+                &record.family,
+                self.refresh_ttl_secs,
+            )
+            .await?;
+        if !first_use {
+            tracing::warn!(
+                "refresh token reused, revoking family {} of {}",
+                record.family,
+                record.sub
+            );
+            self.redis.delete(&family_key(&record.family)).await?;
+            return Err(TokenError::Reused);
+        }
+
+        self.redis
+            .expire(&family_key(&record.family), self.refresh_ttl_secs as i64)
+            .await?;
+        let refresh_token = self.new_refresh(&record).await?;
+        Ok(RefreshGrant {
+            sub: record.sub,
+            scope: record.scope,
+            refresh_token,
+        })
+    }
+
+    /// 吊销 `sub` 的刷新令牌所在的整个家族；令牌无效或属于其他用户时忽略
+    pub async fn revoke_refresh(&self, sub: &str, token: &str) -> Result<(), TokenError> {
+        let record: Option<RefreshRecord> = self
+            .redis
+            .get_json(&refresh_key(&hash_token(token)))
+            .await?;
+        match record {
+            Some(record) if record.sub == sub => {
+                self.redis.delete(&family_key(&record.family)).await?;
+            }
+            Some(record) => {
+                tracing::warn!(
+                    "{} tried to revoke a refresh token of {}, ignored",
+                    sub,
+                    record.sub
+                );
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// 吊销访问令牌，直到它过期为止
+    pub async fn revoke_access(&self, jti: &str, exp: i64) -> Result<(), TokenError> {
+        let ttl = (exp - Utc::now().timestamp()).max(1) as u64;
+        self.redis
+            .set_json(&denylist_key(jti), &exp, Some(ttl))
+            .await?;
+        Ok(())
+    }
+
+    /// 访问令牌是否已被吊销
+    pub async fn is_revoked(&self, jti: &str) -> Result<bool, TokenError> {
+        Ok(self.redis.exists(&denylist_key(jti)).await?)
+    }
+
+    // 在家族中生成一个新的刷新令牌
+    async fn new_refresh(&self, record: &RefreshRecord) -> Result<String, TokenError> {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        self.redis
+            .set_json(
+                &refresh_key(&hash_token(&token)),
+                record,
+                Some(self.refresh_ttl_secs),
+            )
+            .await?;
+        Ok(token)
+    }
+}
+
+// 校验访问令牌的签名、`exp`、`nbf`、`iat`、`iss` 和 `aud`，不检查是否已被吊销
+fn decode_access<T: DeserializeOwned>(
+    keys: &Keys,
+    token: &str,
+    issuer: &str,
+    audience: &str,
+    leeway_secs: u64,
+) -> Result<Claims<T>, AuthError> {
+    let mut validation = Validation::default();
+    validation.leeway = leeway_secs;
+    validation.validate_nbf = true;
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    let claims = keys.decode::<Claims<T>>(token, &validation)?.claims;
+
+    // jsonwebtoken 不检查 iat，在未来签发的令牌说明时钟异常或令牌被伪造
+    if claims.iat > Utc::now().timestamp() + leeway_secs as i64 {
+        return Err(AuthError::ImmatureToken);
+    }
+    Ok(claims)
+}
+
+// 只保存令牌的哈希，Redis 泄露时令牌也无法使用
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn refresh_key(hash: &str) -> String {
+    format!("{}{}", REFRESH_KEY_PREFIX, hash)
+}
+
+fn family_key(family: &str) -> String {
+    format!("{}{}", FAMILY_KEY_PREFIX, family)
+}
+
+fn denylist_key(jti: &str) -> String {
+    format!("{}{}", DENYLIST_KEY_PREFIX, jti)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/jwt/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn key_config(kid: &str, algorithm: &str, private: Option<&str>, public: &str) -> JwtKeyConfig {
+        JwtKeyConfig {
+            kid: kid.to_string(),
+            algorithm: algorithm.to_string(),
+            private_key: private.map(fixture),
+            public_key: fixture(public),
+        }
+    }
+
+    fn keys(configs: Vec<JwtKeyConfig>) -> Keys {
+        Keys::from_config(&JwtConfig {
+            keys: configs,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn claims() -> Claims<AccessClaims> {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub: "alice".to_string(),
+            iss: "axum.rs".to_string(),
+            aud: "axum.rs".to_string(),
+            iat: now,
+            nbf: now,
+            exp: now + 60,
+            jti: Uuid::new_v4().to_string(),
+            custom: AccessClaims::default(),
+        }
+    }
+
+    fn validation() -> Validation {
+        let mut validation = Validation::default();
+        validation.set_audience(&["axum.rs"]);
+        validation
+    }
+
+    fn decode(keys: &Keys, token: &str) -> Result<Claims<AccessClaims>, JwtError> {
+        keys.decode(token, &validation()).map(|data| data.claims)
+    }
+
+    fn decode_base64(value: &str) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(value).unwrap()
+    }
+
+    #[test]
+    fn rsa_public_key_becomes_jwk() {
+        let key = load_key(&key_config("rsa", "RS256", None, "rsa.pub.pem")).unwrap();
+        let jwk = key.jwk.unwrap();
+        assert_eq!(jwk.common.key_id.as_deref(), Some("rsa"));
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert_eq!(jwk.common.public_key_use, Some(PublicKeyUse::Signature));
+        let AlgorithmParameters::RSA(params) = &jwk.algorithm else {
+            panic!("expected RSA parameters, got {:?}", jwk.algorithm);
+        };
+        assert_eq!(params.e, "AQAB");
+        assert_eq!(decode_base64(&params.n).len(), 256);
+
+        // PKCS#1 格式的公钥得到相同的参数
+        let pkcs1 = load_key(&key_config("rsa", "RS256", None, "rsa.pkcs1.pub.pem")).unwrap();
+        assert_eq!(pkcs1.jwk.unwrap().algorithm, jwk.algorithm);
+    }
+
+    #[test]
+    fn ec_public_key_becomes_jwk() {
+        let key = load_key(&key_config("ec", "ES256", None, "ec.pub.pem")).unwrap();
+        let jwk = key.jwk.unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::ES256));
+        let AlgorithmParameters::EllipticCurve(params) = &jwk.algorithm else {
+            panic!("expected EC parameters, got {:?}", jwk.algorithm);
+        };
+        assert_eq!(params.curve, EllipticCurve::P256);
+        assert_eq!(params.x, "9UJ85LQB3yRpVgCOp69lyyokXZtIVozePOjPCrkS858");
+        assert_eq!(params.y, "nHo90Dl1tNsdoup2sestAOGmK0CKvNooDdKc4jVcUyA");
+    }
+
+    #[test]
+    fn ed25519_public_key_becomes_jwk() {
+        let key = load_key(&key_config("ed", "EdDSA", None, "ed25519.pub.pem")).unwrap();
+        let jwk = key.jwk.unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::EdDSA));
+        let AlgorithmParameters::OctetKeyPair(params) = &jwk.algorithm else {
+            panic!("expected OKP parameters, got {:?}", jwk.algorithm);
+        };
+        assert_eq!(params.curve, EllipticCurve::Ed25519);
+        assert_eq!(params.x, "sEB2UoNteJ9y1BtMPr_cjjqWyNLpuKLmnQFAL3fe9q0");
+    }
+
+    #[test]
+    fn rejects_unusable_keys() {
+        // 公钥类型与算法不符
+        assert!(load_key(&key_config("ec", "ES256", None, "ed25519.pub.pem")).is_err());
+        assert!(load_key(&key_config("ed", "EdDSA", None, "ec.pub.pem")).is_err());
+        // 不支持的算法和不存在的文件
+        assert!(load_key(&key_config("hs", "HS256", None, "ec.pub.pem")).is_err());
+        assert!(load_key(&key_config("ec", "ES256", None, "missing.pem")).is_err());
+    }
+
+    #[test]
+    fn jwks_lists_every_public_key() {
+        let keys = keys(vec![
+            key_config("2024-06", "ES256", Some("ec.pem"), "ec.pub.pem"),
+            key_config("2024-05", "ES256", None, "ec-old.pub.pem"),
+            key_config("rsa", "RS256", None, "rsa.pub.pem"),
+        ]);
+        let kids: Vec<_> = keys
+            .jwks()
+            .keys
+            .into_iter()
+            .map(|jwk| jwk.common.key_id.unwrap())
+            .collect();
+        assert_eq!(kids, ["2024-06", "2024-05", "rsa"]);
+
+        // 对称密钥不公开
+        assert!(Keys::from_secret(b"secret").jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn signs_and_verifies_with_each_algorithm() {
+        for (algorithm, private, public) in [
+            ("RS256", "rsa.pem", "rsa.pub.pem"),
+            ("ES256", "ec.pem", "ec.pub.pem"),
+            ("EdDSA", "ed25519.pem", "ed25519.pub.pem"),
+        ] {
+            let keys = keys(vec![key_config("k1", algorithm, Some(private), public)]);
+            let token = keys.encode(&claims()).unwrap();
+            let header = jsonwebtoken::decode_header(&token).unwrap();
+            assert_eq!(header.kid.as_deref(), Some("k1"));
+            assert_eq!(decode(&keys, &token).unwrap().sub, "alice", "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn decodes_tokens_signed_by_rotated_key() {
+        let previous = keys(vec![key_config(
+            "2024-05",
+            "ES256",
+            Some("ec-old.pem"),
+            "ec-old.pub.pem",
+        )]);
+        let token = previous.encode(&claims()).unwrap();
+
+        // 轮换后旧密钥只保留公钥，仍可校验
+        let rotated = keys(vec![
+            key_config("2024-06", "ES256", Some("ec.pem"), "ec.pub.pem"),
+            key_config("2024-05", "ES256", None, "ec-old.pub.pem"),
+        ]);
+        assert_eq!(decode(&rotated, &token).unwrap().sub, "alice");
+
+        // 旧密钥移除后不再接受
+        let current = keys(vec![key_config(
+            "2024-06",
+            "ES256",
+            Some("ec.pem"),
+            "ec.pub.pem",
+        )]);
+        let err = decode(&current, &token).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidSignature);
+
+        // 没有 `kid` 时逐个尝试
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = None;
+        let encoding = EncodingKey::from_ec_pem(&std::fs::read(fixture("ec-old.pem")).unwrap());
+        let token = jsonwebtoken::encode(&header, &claims(), &encoding.unwrap()).unwrap();
+        assert_eq!(decode(&rotated, &token).unwrap().sub, "alice");
+    }
+
+    #[test]
+    fn prefers_key_named_by_kid() {
+        let keys = keys(vec![
+            key_config("2024-06", "ES256", Some("ec.pem"), "ec.pub.pem"),
+            key_config("2024-05", "ES256", None, "ec-old.pub.pem"),
+            key_config("rsa", "RS256", None, "rsa.pub.pem"),
+        ]);
+        let kids = |kid: Option<&str>| -> Vec<String> {
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = kid.map(String::from);
+            keys.candidates(&header)
+                .into_iter()
+                .map(|key| key.kid.clone().unwrap())
+                .collect()
+        };
+        assert_eq!(kids(Some("2024-05")), ["2024-05", "2024-06"]);
+        assert_eq!(kids(Some("2024-06")), ["2024-06", "2024-05"]);
+        // 未知或没有 `kid` 时按配置顺序，且只包含算法相同的密钥
+        assert_eq!(kids(Some("unknown")), ["2024-06", "2024-05"]);
+        assert_eq!(kids(None), ["2024-06", "2024-05"]);
+    }
+
+    #[test]
+    fn rejects_token_with_mismatched_algorithm() {
+        let ec = keys(vec![key_config(
+            "2024-06",
+            "ES256",
+            Some("ec.pem"),
+            "ec.pub.pem",
+        )]);
+
+        // HS256 令牌不能用公钥校验，防止算法混淆
+        let token = Keys::from_secret(b"secret").encode(&claims()).unwrap();
+        let err = decode(&ec, &token).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidAlgorithm);
+
+        // `kid` 相同但算法不同
+        let rsa = keys(vec![key_config(
+            "2024-06",
+            "RS256",
+            Some("rsa.pem"),
+            "rsa.pub.pem",
+        )]);
+        let token = rsa.encode(&claims()).unwrap();
+        let err = decode(&ec, &token).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidAlgorithm);
+        assert!(matches!(AuthError::from(err), AuthError::BadSignature));
+    }
+
+    const LEEWAY: u64 = 60;
+
+    fn check(keys: &Keys, claims: &impl Serialize) -> Result<Claims<AccessClaims>, AuthError> {
+        let token = keys.encode(claims).unwrap();
+        decode_access(keys, &token, "axum.rs", "axum.rs", LEEWAY)
+    }
+
+    #[test]
+    fn accepts_valid_access_token() {
+        let keys = Keys::from_secret(b"secret");
+        assert_eq!(check(&keys, &claims()).unwrap().sub, "alice");
+    }
+
+    #[test]
+    fn applies_leeway_to_time_claims() {
+        let keys = Keys::from_secret(b"secret");
+        let now = Utc::now().timestamp();
+        let skew = LEEWAY as i64 / 2;
+
+        let mut expired = claims();
+        expired.exp = now - skew;
+        assert!(check(&keys, &expired).is_ok());
+        expired.exp = now - 2 * LEEWAY as i64;
+        assert!(matches!(
+            check(&keys, &expired),
+            Err(AuthError::ExpiredToken)
+        ));
+
+        let mut immature = claims();
+        immature.nbf = now + skew;
+        assert!(check(&keys, &immature).is_ok());
+        immature.nbf = now + 2 * LEEWAY as i64;
+        assert!(matches!(
+            check(&keys, &immature),
+            Err(AuthError::ImmatureToken)
+        ));
+
+        let mut future = claims();
+        future.iat = now + skew;
+        assert!(check(&keys, &future).is_ok());
+        future.iat = now + 2 * LEEWAY as i64;
+        assert!(matches!(
+            check(&keys, &future),
+            Err(AuthError::ImmatureToken)
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_issuer_and_audience() {
+        let keys = Keys::from_secret(b"secret");
+
+        let mut wrong_issuer = claims();
+        wrong_issuer.iss = "evil.example".to_string();
+        assert!(matches!(
+            check(&keys, &wrong_issuer),
+            Err(AuthError::WrongIssuer)
+        ));
+
+        let mut wrong_audience = claims();
+        wrong_audience.aud = "other-service".to_string();
+        assert!(matches!(
+            check(&keys, &wrong_audience),
+            Err(AuthError::WrongAudience)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_signature_and_malformed_tokens() {
+        let keys = Keys::from_secret(b"secret");
+        let token = Keys::from_secret(b"other").encode(&claims()).unwrap();
+        let result = decode_access::<AccessClaims>(&keys, &token, "axum.rs", "axum.rs", LEEWAY);
+        assert!(matches!(result, Err(AuthError::BadSignature)));
+
+        let result =
+            decode_access::<AccessClaims>(&keys, "not-a-jwt", "axum.rs", "axum.rs", LEEWAY);
+        assert!(matches!(result, Err(AuthError::MalformedToken)));
+
+        // 缺少必需的声明
+        let now = Utc::now().timestamp();
+        let missing_nbf = json!({
+            "sub": "alice",
+            "iss": "axum.rs",
+            "aud": "axum.rs",
+            "iat": now,
+            "exp": now + 60,
+            "jti": "1",
+        });
+        assert!(matches!(
+            check(&keys, &missing_nbf),
+            Err(AuthError::MalformedToken)
+        ));
+    }
+
+    #[test]
+    fn maps_jwt_errors_to_auth_errors() {
+        let cases = [
+            (ErrorKind::ExpiredSignature, "ExpiredToken"),
+            (ErrorKind::ImmatureSignature, "ImmatureToken"),
+            (ErrorKind::InvalidSignature, "BadSignature"),
+            (ErrorKind::InvalidAlgorithm, "BadSignature"),
+            (ErrorKind::InvalidAudience, "WrongAudience"),
+            (ErrorKind::InvalidIssuer, "WrongIssuer"),
+            (ErrorKind::InvalidToken, "MalformedToken"),
+            (
+                ErrorKind::MissingRequiredClaim("nbf".to_string()),
+                "MalformedToken",
+            ),
+        ];
+        for (kind, expected) in cases {
+            let err = AuthError::from(JwtError::from(kind.clone()));
+            assert_eq!(format!("{:?}", err), expected, "{:?}", kind);
+        }
+    }
+
+    fn challenge(err: AuthError) -> (StatusCode, Option<String>) {
+        let response = err.into_response();
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), challenge)
+    }
+
+    #[test]
+    fn token_errors_carry_www_authenticate() {
+        assert_eq!(
+            challenge(AuthError::ExpiredToken),
+            (
+                StatusCode::UNAUTHORIZED,
+                Some(
+                    r#"Bearer error="invalid_token", error_description="The access token expired""#
+                        .to_string()
+                )
+            )
+        );
+        for err in [
+            AuthError::MalformedToken,
+            AuthError::ImmatureToken,
+            AuthError::BadSignature,
+            AuthError::WrongAudience,
+            AuthError::WrongIssuer,
+            AuthError::RevokedToken,
+        ] {
+            let (status, challenge) = challenge(err);
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert!(challenge
+                .unwrap()
+                .starts_with(r#"Bearer error="invalid_token""#));
+        }
+
+        // 没有提供令牌时只返回认证方式
+        assert_eq!(
+            challenge(AuthError::MissingToken),
+            (StatusCode::UNAUTHORIZED, Some("Bearer".to_string()))
+        );
+        // 不是访问令牌的错误不带 `WWW-Authenticate`
+        assert_eq!(
+            challenge(AuthError::WrongCredentials),
+            (StatusCode::UNAUTHORIZED, None)
+        );
+        assert_eq!(
+            challenge(AuthError::InvalidRefreshToken),
+            (StatusCode::BAD_REQUEST, None)
+        );
+    }
+}
@@ -1,7 +1,5 @@
 #![allow(unused)]
 
-use std::env;
-
 use axum::{
     extract::Multipart,
     http::HeaderMap,
@@ -10,44 +8,65 @@ use axum::{
     Router,
 };
 
-use dotenv::dotenv;
-use serde::Deserialize;
 use tower_http::trace::TraceLayer;
 
+mod config;
 mod logger;
 
+// 下面的模块由各个课程使用，这里声明是为了让 `cargo test` 运行其中的测试
+#[cfg(test)]
+mod api_keys;
+#[cfg(test)]
+mod auth;
+#[cfg(test)]
+mod authz;
+#[cfg(test)]
+mod blob_store;
+#[cfg(test)]
+mod cache;
+#[cfg(test)]
+mod cookie_jar;
+#[cfg(test)]
+mod error;
+#[cfg(test)]
+mod files;
+#[cfg(test)]
+mod idempotency;
+#[cfg(test)]
+mod jwt;
+#[cfg(test)]
+mod redis_client;
+#[cfg(test)]
+mod secure_cookie;
+#[cfg(test)]
+mod session;
+#[cfg(test)]
+mod tus;
+#[cfg(test)]
+mod upload;
+#[cfg(test)]
+mod users;
+
 // 上传文件的页面
 async fn index() {
     println!("123123123")
 }
 
-/// Web配置
-#[derive(Deserialize)]
-pub struct WebConfig {
-    /// Web服务监听地址
-    pub addr: String,
-}
-
-/// Redis 配置
-#[derive(Deserialize)]
-pub struct RedisConfig {
-    /// 连接字符串
-    pub dsn: String,
-}
-
 #[tokio::main]
 async fn main() {
-    // 初始化日志记录器
-    logger::init_logger();
+    // 加载并校验配置，配置无效时打印错误后退出
+    let config = config::AppConfig::global();
 
-    dotenv().ok();
-    let web_addr = env::var("WEB_ADDR").expect("WEB_ADDR is not set.");
+    // 初始化日志记录器
+    logger::init_logger_with(&config.log.level);
 
     let routes = Router::new()
         .route("/", get(index))
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind(web_addr).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(&config.web.addr)
+        .await
+        .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
     axum::serve(listener, routes).await.unwrap();
 }
@@ -1,69 +1,548 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use redis::aio::ConnectionManager;
 use redis::AsyncCommands;
 use redis::Client;
-use std::error::Error;
-
-const REDIS_DSN: &str = "redis://127.0.0.1:6379/";
-
-// TODO 获取 redis 连接
-pub async fn connect_to_redis() -> Result<redis::aio::MultiplexedConnection, String> {
-    let client = Client::open(REDIS_DSN).map_err(|err| err.to_string())?;
-    let conn = client
-        .get_multiplexed_tokio_connection()
-        .await
-        .map_err(|err| err.to_string())?;
-    Ok(conn)
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+use crate::config::{AppConfig, RedisConfig};
+
+/// Redis 存储的错误
+#[derive(Debug)]
+pub enum RedisStoreError {
+    /// 无法连接 Redis，或命令执行失败
+    Connection(redis::RedisError),
+    /// key 不存在
+    NotFound(String),
+    /// 值无法解析为目标类型
+    Parse { key: String, message: String },
+    /// 值序列化或反序列化失败
+    Serialization(String),
 }
 
-// TODO 写入Redis
-pub async fn write_to_redis<T>(key: &String, value: T) -> Result<(), String>
-where
-    T: ToString,
-{
-    let mut conn = connect_to_redis().await?;
-    conn.set(key, value.to_string())
-        .await
-        .map_err(|err| err.to_string())?;
-    Ok(())
+impl Display for RedisStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RedisStoreError::Connection(err) => write!(f, "redis connection error: {}", err),
+            RedisStoreError::NotFound(key) => write!(f, "Key '{}' not found in Redis", key),
+            RedisStoreError::Parse { key, message } => {
+                write!(f, "Failed to parse value of '{}': {}", key, message)
+            }
+            RedisStoreError::Serialization(message) => {
+                write!(f, "Failed to serialize value: {}", message)
+            }
+        }
+    }
 }
 
-// 删除 redis 缓存的值
-pub async fn delete_from_redis(key: &String) -> Result<(), String> {
-    let mut conn = connect_to_redis().await?;
-    let result: i32 = conn.del(key).await.map_err(|err| err.to_string())?;
-    if result == 1 {
-        Ok(())
-    } else {
-        Err(format!("Failed to delete key: {}", key))
+impl std::error::Error for RedisStoreError {}
+
+impl From<redis::RedisError> for RedisStoreError {
+    fn from(err: redis::RedisError) -> Self {
+        RedisStoreError::Connection(err)
     }
 }
 
-// TODO 获取Redis
-pub async fn read_from_redis<T>(key: &String) -> Result<T, String>
-where
-    T: std::str::FromStr,
-    <T as std::str::FromStr>::Err: std::fmt::Display,
-{
-    let mut conn = connect_to_redis().await?;
-    let value_str: Option<String> = conn.get(key).await.map_err(|err| err.to_string())?;
-    match value_str {
-        Some(value) => {
-            let parsed_value: T = value
-                .parse()
-                .map_err(|err| format!("Failed to parse value: {}", err))?;
-            Ok(parsed_value)
+impl From<serde_json::Error> for RedisStoreError {
+    fn from(err: serde_json::Error) -> Self {
+        RedisStoreError::Serialization(err.to_string())
+    }
+}
+
+impl IntoResponse for RedisStoreError {
+    fn into_response(self) -> Response {
+        let (status, code) = match &self {
+            RedisStoreError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            RedisStoreError::Connection(_) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "redis_unavailable")
+            }
+            RedisStoreError::Parse { .. } => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "redis_parse_error")
+            }
+            RedisStoreError::Serialization(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "redis_serialization_error",
+            ),
+        };
+        // 服务端错误只记录日志，不把内部细节返回给客户端
+        let (status_text, message) = if status.is_server_error() {
+            tracing::error!("{}", self);
+            let reason = status.canonical_reason().unwrap_or("Internal Server Error");
+            ("error", reason.to_string())
+        } else {
+            ("fail", self.to_string())
+        };
+        let body = Json(json!({
+            "status": status_text,
+            "code": code,
+            "message": message,
+        }));
+        (status, body).into_response()
+    }
+}
+
+/// 值的编码方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// JSON，可读性好，方便在 redis-cli 中查看
+    Json,
+    /// MessagePack，体积更小、编解码更快
+    MessagePack,
+}
+
+impl Codec {
+    /// 将值编码为字节
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, RedisStoreError> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            // 结构体按 map 编码，字段顺序变化时仍然可以解码
+            Codec::MessagePack => rmp_serde::to_vec_named(value)
+                .map_err(|err| RedisStoreError::Serialization(err.to_string())),
+        }
+    }
+
+    /// 将字节解码为值
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, RedisStoreError> {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            Codec::MessagePack => rmp_serde::from_slice(bytes)
+                .map_err(|err| RedisStoreError::Serialization(err.to_string())),
+        }
+    }
+}
+
+/// 按 key 前缀选择编码方式的规则表，未匹配的 key 使用 JSON
+#[derive(Clone, Debug, Default)]
+struct Codecs(Vec<(String, Codec)>);
+
+impl Codecs {
+    // 同一个前缀只保留最后一次设置
+    fn with(mut self, prefix: &str, codec: Codec) -> Self {
+        self.0.retain(|(p, _)| p != prefix);
+        self.0.push((prefix.to_string(), codec));
+        self
+    }
+
+    // 多个前缀都匹配时取最长的那个
+    fn codec_for(&self, key: &str) -> Codec {
+        self.0
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, codec)| *codec)
+            .unwrap_or(Codec::Json)
+    }
+
+    // mset 前的编码，每个 key 按自己的编码方式
+    fn encode_items<'k, T: Serialize>(
+        &self,
+        items: &[(&'k str, T)],
+    ) -> Result<Vec<(&'k str, Vec<u8>)>, RedisStoreError> {
+        items
+            .iter()
+            .map(|(key, value)| Ok((*key, self.codec_for(key).encode(value)?)))
+            .collect()
+    }
+
+    // mget 后的解码，结果与 keys 一一对应
+    fn decode_values<T: DeserializeOwned>(
+        &self,
+        keys: &[&str],
+        values: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<Option<T>>, RedisStoreError> {
+        keys.iter()
+            .zip(values)
+            .map(|(key, bytes)| {
+                bytes
+                    .map(|bytes| self.codec_for(key).decode(&bytes))
+                    .transpose()
+            })
+            .collect()
+    }
+}
+
+/// Redis 连接池
+///
+/// 持有固定数量的 `ConnectionManager`，按轮询方式分配。每个 `ConnectionManager`
+/// 内部是一个多路复用连接，断开后会按指数退避自动重连。
+/// 克隆的代价很低，可以直接放到 axum 的 state 中。
+#[derive(Clone)]
+pub struct RedisPool {
+    managers: Arc<Vec<ConnectionManager>>,
+    next: Arc<AtomicUsize>,
+    /// 按 key 前缀指定的编码方式，未匹配的 key 使用 JSON
+    codecs: Arc<Codecs>,
+}
+
+impl RedisPool {
+    /// 按全局配置创建连接池
+    pub async fn from_config() -> Result<Self, RedisStoreError> {
+        Self::new(&AppConfig::global().redis).await
+    }
+
+    /// 创建连接池，所有连接都建立成功后才返回
+    pub async fn new(config: &RedisConfig) -> Result<Self, RedisStoreError> {
+        let client = Client::open(config.dsn.as_str())?;
+        let mut managers = Vec::with_capacity(config.pool_size);
+        for _ in 0..config.pool_size {
+            let manager = ConnectionManager::new_with_backoff_and_timeouts(
+                client.clone(),
+                config.retry_exponent_base,
+                config.retry_factor,
+                config.retries,
+                Duration::from_millis(config.response_timeout_ms),
+                Duration::from_millis(config.connection_timeout_ms),
+            )
+            .await?;
+            managers.push(manager);
+        }
+        Ok(Self {
+            managers: Arc::new(managers),
+            next: Arc::new(AtomicUsize::new(0)),
+            codecs: Arc::new(Codecs::default()),
+        })
+    }
+
+    /// 没有任何连接的连接池，只用于不会访问 Redis 的测试
+    #[cfg(test)]
+    pub(crate) fn disconnected() -> Self {
+        Self {
+            managers: Arc::new(vec![]),
+            next: Arc::new(AtomicUsize::new(0)),
+            codecs: Arc::new(Codecs::default()),
+        }
+    }
+
+    /// 为指定前缀的 key 设置编码方式，多个前缀都匹配时取最长的那个
+    pub fn with_codec(mut self, prefix: &str, codec: Codec) -> Self {
+        self.codecs = Arc::new(self.codecs.as_ref().clone().with(prefix, codec));
+        self
+    }
+
+    /// key 对应的编码方式
+    pub fn codec_for(&self, key: &str) -> Codec {
+        self.codecs.codec_for(key)
+    }
+
+    /// 获取一个连接
+    pub fn get(&self) -> ConnectionManager {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.managers.len();
+        self.managers[index].clone()
+    }
+
+    // TODO 写入Redis
+    pub async fn write<T>(&self, key: &str, value: T) -> Result<(), RedisStoreError>
+    where
+        T: ToString,
+    {
+        let mut conn = self.get();
+        conn.set::<_, _, ()>(key, value.to_string()).await?;
+        Ok(())
+    }
+
+    // 删除 redis 缓存的值，返回 key 之前是否存在；key 不存在不算错误
+    pub async fn delete(&self, key: &str) -> Result<bool, RedisStoreError> {
+        let mut conn = self.get();
+        let result: i32 = conn.del(key).await?;
+        Ok(result > 0)
+    }
+
+    // TODO 获取Redis
+    pub async fn read<T>(&self, key: &str) -> Result<T, RedisStoreError>
+    where
+        T: std::str::FromStr,
+        <T as std::str::FromStr>::Err: std::fmt::Display,
+    {
+        let mut conn = self.get();
+        let value_str: Option<String> = conn.get(key).await?;
+        match value_str {
+            Some(value) => {
+                let parsed_value: T =
+                    value
+                        .parse()
+                        .map_err(|err: T::Err| RedisStoreError::Parse {
+                            key: key.to_string(),
+                            message: err.to_string(),
+                        })?;
+                Ok(parsed_value)
+            }
+            None => Err(RedisStoreError::NotFound(key.to_string())),
+        }
+    }
+
+    // TODO 设置自动过期
+    pub async fn write_ex<T>(
+        &self,
+        key: &str,
+        value: T,
+        expire_seconds: u64,
+    ) -> Result<(), RedisStoreError>
+    where
+        T: ToString,
+    {
+        let mut conn = self.get();
+        conn.set_ex::<_, _, ()>(key, value.to_string(), expire_seconds)
+            .await?;
+        Ok(())
+    }
+
+    /// 以 JSON 读取，key 不存在时返回 `None`
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<T>, RedisStoreError> {
+        self.get_with_codec(key, Codec::Json).await
+    }
+
+    /// 以 JSON 写入，`ttl` 为过期秒数，`None` 表示不过期
+    pub async fn set_json<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<u64>,
+    ) -> Result<(), RedisStoreError> {
+        self.set_with_codec(key, value, ttl, Codec::Json).await
+    }
+
+    /// 按 key 前缀对应的编码方式读取，key 不存在时返回 `None`
+    pub async fn get_typed<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<T>, RedisStoreError> {
+        self.get_with_codec(key, self.codec_for(key)).await
+    }
+
+    /// 按 key 前缀对应的编码方式写入
+    pub async fn set_typed<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<u64>,
+    ) -> Result<(), RedisStoreError> {
+        self.set_with_codec(key, value, ttl, self.codec_for(key))
+            .await
+    }
+
+    /// 以指定的编码方式读取
+    pub async fn get_with_codec<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        codec: Codec,
+    ) -> Result<Option<T>, RedisStoreError> {
+        let mut conn = self.get();
+        let bytes: Option<Vec<u8>> = conn.get(key).await?;
+        bytes.map(|bytes| codec.decode(&bytes)).transpose()
+    }
+
+    /// 以指定的编码方式写入
+    pub async fn set_with_codec<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<u64>,
+        codec: Codec,
+    ) -> Result<(), RedisStoreError> {
+        let bytes = codec.encode(value)?;
+        let mut conn = self.get();
+        match ttl {
+            Some(ttl) => conn.set_ex::<_, _, ()>(key, bytes, ttl).await?,
+            None => conn.set::<_, _, ()>(key, bytes).await?,
+        }
+        Ok(())
+    }
+
+    /// 批量读取，每个 key 按前缀对应的编码方式解码，结果与 `keys` 一一对应
+    pub async fn mget<T: DeserializeOwned>(
+        &self,
+        keys: &[&str],
+    ) -> Result<Vec<Option<T>>, RedisStoreError> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut conn = self.get();
+        let values: Vec<Option<Vec<u8>>> =
+            redis::cmd("MGET").arg(keys).query_async(&mut conn).await?;
+        self.codecs.decode_values(keys, values)
+    }
+
+    /// 批量写入，每个 key 按前缀对应的编码方式编码
+    pub async fn mset<T: Serialize>(&self, items: &[(&str, T)]) -> Result<(), RedisStoreError> {
+        if items.is_empty() {
+            return Ok(());
+        }
+        let items = self.codecs.encode_items(items)?;
+        let mut conn = self.get();
+        conn.mset::<_, _, ()>(&items).await?;
+        Ok(())
+    }
+
+    /// 将 key 的值加上 `delta`，返回新值；key 不存在时从 0 开始
+    pub async fn incr(&self, key: &str, delta: i64) -> Result<i64, RedisStoreError> {
+        let mut conn = self.get();
+        Ok(conn.incr(key, delta).await?)
+    }
+
+    /// 计数器加上 `delta` 并返回新值；key 不存在时先以 0 创建并设置过期秒数。
+    /// 两条命令在同一个事务中执行，不会留下没有过期时间的计数器
+    pub async fn incr_with_ttl(
+        &self,
+        key: &str,
+        delta: i64,
+        ttl: u64,
+    ) -> Result<i64, RedisStoreError> {
+        let mut conn = self.get();
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(key)
+            .arg(0)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .ignore()
+            .incr(key, delta)
+            .query_async(&mut conn)
+            .await?;
+        Ok(count)
+    }
+
+    /// 设置过期秒数，key 不存在时返回 `false`
+    pub async fn expire(&self, key: &str, seconds: i64) -> Result<bool, RedisStoreError> {
+        let mut conn = self.get();
+        Ok(conn.expire(key, seconds).await?)
+    }
+
+    /// 剩余的过期秒数，`None` 表示永不过期；key 不存在时返回 `NotFound`
+    pub async fn ttl(&self, key: &str) -> Result<Option<u64>, RedisStoreError> {
+        let mut conn = self.get();
+        let ttl: i64 = conn.ttl(key).await?;
+        match ttl {
+            -2 => Err(RedisStoreError::NotFound(key.to_string())),
+            -1 => Ok(None),
+            ttl => Ok(Some(ttl as u64)),
         }
-        None => Err(format!("Key '{}' not found in Redis", key)),
+    }
+
+    /// key 不存在时才写入并设置过期秒数，返回是否写入成功
+    pub async fn set_nx(&self, key: &str, value: &str, ttl: u64) -> Result<bool, RedisStoreError> {
+        let mut conn = self.get();
+        let result: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .query_async(&mut conn)
+            .await?;
+        Ok(result.is_some())
+    }
+
+    /// key 是否存在
+    pub async fn exists(&self, key: &str) -> Result<bool, RedisStoreError> {
+        let mut conn = self.get();
+        Ok(conn.exists(key).await?)
+    }
+
+    /// 向集合中添加成员，返回新添加的个数
+    pub async fn sadd(&self, key: &str, member: &str) -> Result<usize, RedisStoreError> {
+        let mut conn = self.get();
+        Ok(conn.sadd(key, member).await?)
+    }
+
+    /// 从集合中删除成员，返回删除的个数
+    pub async fn srem(&self, key: &str, members: &[String]) -> Result<usize, RedisStoreError> {
+        if members.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self.get();
+        Ok(conn.srem(key, members).await?)
+    }
+
+    /// 集合中的所有成员，key 不存在时返回空
+    pub async fn smembers(&self, key: &str) -> Result<Vec<String>, RedisStoreError> {
+        let mut conn = self.get();
+        Ok(conn.smembers(key).await?)
     }
 }
 
-// TODO 设置自动过期
-pub async fn write_ex_to_redis<T>(key: String, value: T, expire_seconds: u64) -> Result<(), String>
-where
-    T: ToString,
-{
-    let mut conn = connect_to_redis().await?;
-    conn.set_ex(key, value.to_string(), expire_seconds)
-        .await
-        .map_err(|err| err.to_string())?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        id: i32,
+        name: String,
+    }
+
+    fn profile(id: i32) -> Profile {
+        Profile {
+            id,
+            name: format!("user-{id}"),
+        }
+    }
+
+    #[test]
+    fn unmatched_keys_use_json() {
+        let codecs = Codecs::default().with("session:", Codec::MessagePack);
+        assert_eq!(codecs.codec_for("profile:1"), Codec::Json);
+        assert_eq!(codecs.codec_for("session:1"), Codec::MessagePack);
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let codecs = Codecs::default()
+            .with("user:", Codec::MessagePack)
+            .with("user:profile:", Codec::Json);
+        assert_eq!(codecs.codec_for("user:1"), Codec::MessagePack);
+        assert_eq!(codecs.codec_for("user:profile:1"), Codec::Json);
+    }
+
+    #[test]
+    fn same_prefix_is_replaced() {
+        let codecs = Codecs::default()
+            .with("user:", Codec::MessagePack)
+            .with("user:", Codec::Json);
+        assert_eq!(codecs.0.len(), 1);
+        assert_eq!(codecs.codec_for("user:1"), Codec::Json);
+    }
+
+    #[test]
+    fn mixed_codecs_round_trip() {
+        let codecs = Codecs::default().with("mp:", Codec::MessagePack);
+        let items = [("json:1", profile(1)), ("mp:2", profile(2))];
+        let encoded = codecs.encode_items(&items).unwrap();
+
+        assert_eq!(encoded[0].0, "json:1");
+        assert_eq!(encoded[0].1, serde_json::to_vec(&profile(1)).unwrap());
+        assert_eq!(encoded[1].1, rmp_serde::to_vec_named(&profile(2)).unwrap());
+
+        // 模拟 MGET 的返回，中间的 key 不存在
+        let keys = ["json:1", "json:missing", "mp:2"];
+        let values = vec![Some(encoded[0].1.clone()), None, Some(encoded[1].1.clone())];
+        let decoded: Vec<Option<Profile>> = codecs.decode_values(&keys, values).unwrap();
+        assert_eq!(decoded, vec![Some(profile(1)), None, Some(profile(2))]);
+    }
+
+    #[test]
+    fn decoding_with_the_wrong_codec_fails() {
+        let codecs = Codecs::default().with("mp:", Codec::MessagePack);
+        let bytes = serde_json::to_vec(&profile(1)).unwrap();
+        let result = codecs.decode_values::<Profile>(&["mp:1"], vec![Some(bytes)]);
+        assert!(matches!(result, Err(RedisStoreError::Serialization(_))));
+    }
 }
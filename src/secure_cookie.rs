@@ -0,0 +1,310 @@
+use std::sync::OnceLock;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+use tower_cookies::{
+    cookie::{time::Duration, SameSite},
+    Cookie, Cookies, Key,
+};
+
+use crate::config::{AppConfig, CookieConfig};
+
+static COOKIE_KEYS: OnceLock<CookieKeys> = OnceLock::new();
+static COOKIE_POLICY: OnceLock<CookiePolicy> = OnceLock::new();
+
+/// 签名和加密 Cookie 使用的密钥
+///
+/// `current` 用于签发；`previous` 是轮换下来的旧密钥，只用于校验，
+/// 用旧密钥校验通过的 Cookie 会用当前密钥重新签发。
+pub struct CookieKeys {
+    pub current: Key,
+    pub previous: Vec<Key>,
+}
+
+impl CookieKeys {
+    /// 按全局配置加载，配置已经过校验
+    pub fn global() -> &'static CookieKeys {
+        COOKIE_KEYS.get_or_init(|| CookieKeys::new(&AppConfig::global().cookie.keys))
+    }
+
+    /// 从十六进制编码的密钥创建，第一个为当前密钥
+    pub fn new(keys: &[String]) -> Self {
+        let mut keys = keys
+            .iter()
+            .map(|key| Key::from(&hex::decode(key).expect("cookie key must be hex encoded")));
+        let current = keys.next().expect("at least one cookie key is required");
+        Self {
+            current,
+            previous: keys.collect(),
+        }
+    }
+}
+
+/// Cookie 的安全属性
+#[derive(Debug, Clone)]
+pub struct CookiePolicy {
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+    pub max_age: Option<Duration>,
+}
+
+impl CookiePolicy {
+    /// 按全局配置创建
+    pub fn global() -> &'static CookiePolicy {
+        COOKIE_POLICY.get_or_init(|| CookiePolicy::new(&AppConfig::global().cookie))
+    }
+
+    pub fn new(config: &CookieConfig) -> Self {
+        let same_site = match config.same_site.to_ascii_lowercase().as_str() {
+            "strict" => SameSite::Strict,
+            "none" => SameSite::None,
+            _ => SameSite::Lax,
+        };
+        Self {
+            secure: config.secure,
+            http_only: config.http_only,
+            same_site,
+            max_age: config.max_age_secs.map(Duration::seconds),
+        }
+    }
+
+    /// 按策略创建 Cookie，作用于整个站点
+    pub fn build(&self, name: &str, value: impl Into<String>) -> Cookie<'static> {
+        let mut cookie = Cookie::build((name.to_string(), value.into()))
+            .path("/")
+            .secure(self.secure)
+            .http_only(self.http_only)
+            .same_site(self.same_site)
+            .build();
+        if let Some(max_age) = self.max_age {
+            cookie.set_max_age(max_age);
+        }
+        cookie
+    }
+}
+
+/// 签名和加密的 Cookie
+///
+/// - 签名（HMAC）：客户端可以看到值，但无法篡改
+/// - 加密（AEAD）：客户端既看不到也无法篡改
+///
+/// 需要 `CookieManagerLayer`。
+pub struct SecureCookies {
+    cookies: Cookies,
+    keys: &'static CookieKeys,
+    policy: &'static CookiePolicy,
+}
+
+impl SecureCookies {
+    /// 读取签名 Cookie，校验失败时返回 `None`
+    pub fn get_signed(&self, name: &str) -> Option<String> {
+        if let Some(cookie) = self.cookies.signed(&self.keys.current).get(name) {
+            return Some(cookie.value().to_string());
+        }
+        let value = self
+            .keys
+            .previous
+            .iter()
+            .find_map(|key| self.cookies.signed(key).get(name))?
+            .value()
+            .to_string();
+        // 旧密钥签发的，换成当前密钥
+        self.add_signed(name, value.clone());
+        Some(value)
+    }
+
+    /// 写入签名 Cookie
+    pub fn add_signed(&self, name: &str, value: impl Into<String>) {
+        self.cookies
+            .signed(&self.keys.current)
+            .add(self.policy.build(name, value));
+    }
+
+    /// 读取加密 Cookie，解密失败时返回 `None`
+    pub fn get_private(&self, name: &str) -> Option<String> {
+        if let Some(cookie) = self.cookies.private(&self.keys.current).get(name) {
+            return Some(cookie.value().to_string());
+        }
+        let value = self
+            .keys
+            .previous
+            .iter()
+            .find_map(|key| self.cookies.private(key).get(name))?
+            .value()
+            .to_string();
+        // 旧密钥加密的，换成当前密钥
+        self.add_private(name, value.clone());
+        Some(value)
+    }
+
+    /// 写入加密 Cookie
+    pub fn add_private(&self, name: &str, value: impl Into<String>) {
+        self.cookies
+            .private(&self.keys.current)
+            .add(self.policy.build(name, value));
+    }
+
+    /// 让客户端删除 Cookie
+    pub fn remove(&self, name: &str) {
+        self.cookies.remove(self.policy.build(name, ""));
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SecureCookies
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let cookies = Cookies::from_request_parts(parts, state).await?;
+        Ok(Self {
+            cookies,
+            keys: CookieKeys::global(),
+            policy: CookiePolicy::global(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_key(byte: u8) -> String {
+        hex::encode([byte; 64])
+    }
+
+    // 第一个为当前密钥，其余为旧密钥
+    fn secure_cookies(cookies: &Cookies, keys: &[u8]) -> SecureCookies {
+        let keys: Vec<String> = keys.iter().map(|byte| hex_key(*byte)).collect();
+        SecureCookies {
+            cookies: cookies.clone(),
+            keys: Box::leak(Box::new(CookieKeys::new(&keys))),
+            policy: Box::leak(Box::new(CookiePolicy::new(&CookieConfig::default()))),
+        }
+    }
+
+    // 把 Cookie 的值改掉一个字符
+    fn tamper(cookies: &Cookies, name: &str) {
+        let value = cookies.get(name).unwrap().value().to_string();
+        let mut bytes = value.into_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] = if bytes[last] == b'A' { b'B' } else { b'A' };
+        cookies.add(Cookie::new(
+            name.to_string(),
+            String::from_utf8(bytes).unwrap(),
+        ));
+    }
+
+    #[test]
+    fn signed_round_trip() {
+        let cookies = Cookies::default();
+        let jar = secure_cookies(&cookies, &[1]);
+        jar.add_signed("theme", "dark");
+        assert_eq!(jar.get_signed("theme").as_deref(), Some("dark"));
+        // 签名 Cookie 的值对客户端可见
+        assert!(cookies.get("theme").unwrap().value().contains("dark"));
+    }
+
+    #[test]
+    fn private_round_trip() {
+        let cookies = Cookies::default();
+        let jar = secure_cookies(&cookies, &[1]);
+        jar.add_private("token", "secret");
+        assert_eq!(jar.get_private("token").as_deref(), Some("secret"));
+        assert!(!cookies.get("token").unwrap().value().contains("secret"));
+    }
+
+    #[test]
+    fn signed_with_rotated_key_is_reissued() {
+        let cookies = Cookies::default();
+        secure_cookies(&cookies, &[1]).add_signed("theme", "dark");
+
+        let jar = secure_cookies(&cookies, &[2, 1]);
+        assert_eq!(jar.get_signed("theme").as_deref(), Some("dark"));
+
+        let current = Key::from(&[2; 64]);
+        let reissued = cookies.signed(&current).get("theme").unwrap();
+        assert_eq!(reissued.value(), "dark");
+    }
+
+    #[test]
+    fn private_with_rotated_key_is_reissued() {
+        let cookies = Cookies::default();
+        secure_cookies(&cookies, &[1]).add_private("token", "secret");
+
+        let jar = secure_cookies(&cookies, &[2, 1]);
+        assert_eq!(jar.get_private("token").as_deref(), Some("secret"));
+
+        let current = Key::from(&[2; 64]);
+        let reissued = cookies.private(&current).get("token").unwrap();
+        assert_eq!(reissued.value(), "secret");
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let cookies = Cookies::default();
+        secure_cookies(&cookies, &[1]).add_signed("theme", "dark");
+        secure_cookies(&cookies, &[1]).add_private("token", "secret");
+
+        let jar = secure_cookies(&cookies, &[2]);
+        assert_eq!(jar.get_signed("theme"), None);
+        assert_eq!(jar.get_private("token"), None);
+    }
+
+    #[test]
+    fn tampered_cookies_are_rejected() {
+        let cookies = Cookies::default();
+        let jar = secure_cookies(&cookies, &[2, 1]);
+        jar.add_signed("theme", "dark");
+        jar.add_private("token", "secret");
+
+        tamper(&cookies, "theme");
+        tamper(&cookies, "token");
+        assert_eq!(jar.get_signed("theme"), None);
+        assert_eq!(jar.get_private("token"), None);
+    }
+
+    #[test]
+    fn policy_from_config() {
+        let config = CookieConfig {
+            secure: true,
+            http_only: true,
+            same_site: "Strict".to_string(),
+            max_age_secs: Some(3600),
+            ..CookieConfig::default()
+        };
+        let cookie = CookiePolicy::new(&config).build("sid", "1");
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert_eq!(cookie.max_age(), Some(Duration::hours(1)));
+        assert_eq!(cookie.path(), Some("/"));
+
+        let config = CookieConfig {
+            secure: false,
+            http_only: false,
+            same_site: "none".to_string(),
+            ..CookieConfig::default()
+        };
+        let cookie = CookiePolicy::new(&config).build("sid", "1");
+        assert_eq!(cookie.secure(), Some(false));
+        assert_eq!(cookie.http_only(), Some(false));
+        assert_eq!(cookie.same_site(), Some(SameSite::None));
+        assert_eq!(cookie.max_age(), None);
+    }
+
+    #[test]
+    fn unknown_same_site_falls_back_to_lax() {
+        let config = CookieConfig {
+            same_site: "bogus".to_string(),
+            ..CookieConfig::default()
+        };
+        assert_eq!(CookiePolicy::new(&config).same_site, SameSite::Lax);
+    }
+}
@@ -0,0 +1,820 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value as JsonValue};
+use sqlx::{types::Json as SqlJson, Pool, Postgres, Row};
+use tokio::sync::Mutex as AsyncMutex;
+use tower::{Layer, Service};
+use tower_cookies::cookie::Cookie;
+use uuid::Uuid;
+
+use crate::cookie_jar::CookieJar;
+use crate::redis_client::{RedisPool, RedisStoreError};
+use crate::secure_cookie::CookiePolicy;
+
+/// 默认的 Session ID Cookie 名称
+pub const DEFAULT_COOKIE_NAME: &str = "axum_rs_session_id";
+/// 默认的 Session 有效期（秒），每次访问后重新计算
+pub const DEFAULT_TTL_SECS: u64 = 1200;
+/// Redis 中 Session 的 key 前缀
+const REDIS_KEY_PREFIX: &str = "axum_rs_session:";
+/// Redis 中用户 Session 集合的 key 前缀
+const USER_SESSIONS_KEY_PREFIX: &str = "axum_rs_user_sessions:";
+
+/// Session 中保存的数据
+pub type SessionData = HashMap<String, JsonValue>;
+
+/// Session 的错误
+#[derive(Debug)]
+pub enum SessionError {
+    /// Redis 存储出错
+    Redis(RedisStoreError),
+    /// Postgres 存储出错
+    Database(sqlx::Error),
+    /// 值序列化或反序列化失败
+    Serialization(serde_json::Error),
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Redis(err) => write!(f, "session store error: {}", err),
+            SessionError::Database(err) => write!(f, "session store error: {}", err),
+            SessionError::Serialization(err) => write!(f, "invalid session value: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<RedisStoreError> for SessionError {
+    fn from(err: RedisStoreError) -> Self {
+        SessionError::Redis(err)
+    }
+}
+
+impl From<sqlx::Error> for SessionError {
+    fn from(err: sqlx::Error) -> Self {
+        SessionError::Database(err)
+    }
+}
+
+impl From<serde_json::Error> for SessionError {
+    fn from(err: serde_json::Error) -> Self {
+        SessionError::Serialization(err)
+    }
+}
+
+impl IntoResponse for SessionError {
+    fn into_response(self) -> Response {
+        match self {
+            SessionError::Redis(err) => err.into_response(),
+            err => {
+                tracing::error!("{}", err);
+                let body = Json(json!({
+                    "status": "error",
+                    "code": "session_error",
+                    "message": "Internal Server Error",
+                }));
+                (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+            }
+        }
+    }
+}
+
+/// Session 存储
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    /// 读取 Session，不存在或已过期时返回 `None`
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, SessionError>;
+    /// 保存 Session，`ttl` 秒后过期
+    async fn save(&self, id: &str, data: &SessionData, ttl: u64) -> Result<(), SessionError>;
+    /// 只续期，不修改数据
+    async fn touch(&self, id: &str, ttl: u64) -> Result<(), SessionError>;
+    /// 删除 Session
+    async fn delete(&self, id: &str) -> Result<(), SessionError>;
+}
+
+/// 保存在 Redis 中的 Session
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    redis: RedisPool,
+}
+
+impl RedisSessionStore {
+    pub fn new(redis: RedisPool) -> Self {
+        Self { redis }
+    }
+
+    fn key(id: &str) -> String {
+        format!("{}{}", REDIS_KEY_PREFIX, id)
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, SessionError> {
+        Ok(self.redis.get_json(&Self::key(id)).await?)
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: u64) -> Result<(), SessionError> {
+        Ok(self.redis.set_json(&Self::key(id), data, Some(ttl)).await?)
+    }
+
+    async fn touch(&self, id: &str, ttl: u64) -> Result<(), SessionError> {
+        self.redis.expire(&Self::key(id), ttl as i64).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), SessionError> {
+        self.redis.delete(&Self::key(id)).await?;
+        Ok(())
+    }
+}
+
+/// 保存在内存中的 Session，只适合单进程和测试
+#[derive(Clone, Default)]
+pub struct MemorySessionStore {
+    sessions: Arc<Mutex<HashMap<String, (SessionData, Instant)>>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some((data, expires_at)) if *expires_at > Instant::now() => Ok(Some(data.clone())),
+            Some(_) => {
+                sessions.remove(id);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: u64) -> Result<(), SessionError> {
+        let expires_at = Instant::now() + Duration::from_secs(ttl);
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(id.to_string(), (data.clone(), expires_at));
+        Ok(())
+    }
+
+    async fn touch(&self, id: &str, ttl: u64) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some((_, expires_at)) = sessions.get_mut(id) {
+            *expires_at = Instant::now() + Duration::from_secs(ttl);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), SessionError> {
+        self.sessions.lock().unwrap().remove(id);
+        Ok(())
+    }
+}
+
+/// 保存在 Postgres `sessions` 表中的 Session
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    db: Pool<Postgres>,
+}
+
+impl PostgresSessionStore {
+    pub fn new(db: Pool<Postgres>) -> Self {
+        Self { db }
+    }
+
+    /// 清理已过期的 Session，返回清理的条数
+    pub async fn delete_expired(&self) -> Result<u64, SessionError> {
+        let result = sqlx::query("DELETE FROM sessions WHERE expires_at <= now()")
+            .execute(&self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+// ttl 秒之后的时间
+fn expires_at(ttl: u64) -> chrono::DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl as i64)
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, SessionError> {
+        let row = sqlx::query("SELECT data FROM sessions WHERE id = $1 AND expires_at > now()")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row.map(|row| row.get::<SqlJson<SessionData>, _>("data").0))
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: u64) -> Result<(), SessionError> {
+        sqlx::query(
+            "INSERT INTO sessions (id, data, expires_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (id) DO UPDATE SET data = EXCLUDED.data, expires_at = EXCLUDED.expires_at",
+        )
+        .bind(id)
+        .bind(SqlJson(data))
+        .bind(expires_at(ttl))
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn touch(&self, id: &str, ttl: u64) -> Result<(), SessionError> {
+        sqlx::query("UPDATE sessions SET expires_at = $1 WHERE id = $2")
+            .bind(expires_at(ttl))
+            .bind(id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), SessionError> {
+        sqlx::query("DELETE FROM sessions WHERE id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+/// 用户的所有 Session
+///
+/// 在 Redis 集合 `axum_rs_user_sessions:{user}` 中记录用户登录过的 Session ID，
+/// 用于列出用户的所有 Session，以及“退出所有设备”。
+/// 已过期的 Session ID 在读取时清理。
+#[derive(Clone)]
+pub struct UserSessions {
+    redis: RedisPool,
+    store: Arc<dyn SessionStore>,
+}
+
+impl UserSessions {
+    /// `store` 需要与 [`SessionLayer`] 使用同一个存储
+    pub fn new(redis: RedisPool, store: impl SessionStore) -> Self {
+        Self {
+            redis,
+            store: Arc::new(store),
+        }
+    }
+
+    fn key(user: &str) -> String {
+        format!("{}{}", USER_SESSIONS_KEY_PREFIX, user)
+    }
+
+    /// 记录用户的一个 Session
+    pub async fn add(&self, user: &str, session_id: &str) -> Result<(), SessionError> {
+        self.redis.sadd(&Self::key(user), session_id).await?;
+        Ok(())
+    }
+
+    /// 移除用户的一个 Session，不会删除 Session 本身
+    pub async fn remove(&self, user: &str, session_id: &str) -> Result<(), SessionError> {
+        self.redis
+            .srem(&Self::key(user), &[session_id.to_string()])
+            .await?;
+        Ok(())
+    }
+
+    /// 用户所有仍然有效的 Session ID
+    pub async fn list(&self, user: &str) -> Result<Vec<String>, SessionError> {
+        let key = Self::key(user);
+        let mut alive = vec![];
+        let mut expired = vec![];
+        for id in self.redis.smembers(&key).await? {
+            if self.store.load(&id).await?.is_some() {
+                alive.push(id);
+            } else {
+                expired.push(id);
+            }
+        }
+        self.redis.srem(&key, &expired).await?;
+        Ok(alive)
+    }
+
+    /// 删除用户的所有 Session，返回删除的个数
+    pub async fn revoke_all(&self, user: &str) -> Result<usize, SessionError> {
+        let key = Self::key(user);
+        let ids = self.redis.smembers(&key).await?;
+        for id in &ids {
+            self.store.delete(id).await?;
+        }
+        self.redis.delete(&key).await?;
+        Ok(ids.len())
+    }
+}
+
+struct SessionState {
+    /// 客户端带来的或新生成的 Session ID
+    id: Option<String>,
+    /// `None` 表示还没有从存储中加载
+    data: Option<SessionData>,
+    /// 数据是否被修改过
+    dirty: bool,
+    /// ID 是新生成的，需要下发给客户端
+    new_id: bool,
+    /// Session 已被销毁，需要让客户端删除 Cookie
+    destroyed: bool,
+}
+
+// 响应时对 Session Cookie 的处理
+enum CookieUpdate {
+    Keep,
+    Set(String),
+    Expire,
+}
+
+/// 当前请求的 Session
+///
+/// 第一次读写时才从存储中加载；只有修改过才会在响应时保存，
+/// 否则只续期。需要配合 [`SessionLayer`] 使用。
+#[derive(Clone)]
+pub struct Session {
+    state: Arc<AsyncMutex<SessionState>>,
+    store: Arc<dyn SessionStore>,
+}
+
+impl Session {
+    pub(crate) fn new(id: Option<String>, store: Arc<dyn SessionStore>) -> Self {
+        Self {
+            state: Arc::new(AsyncMutex::new(SessionState {
+                id,
+                data: None,
+                dirty: false,
+                new_id: false,
+                destroyed: false,
+            })),
+            store,
+        }
+    }
+
+    /// 读取一个值，不存在时返回 `None`
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, SessionError> {
+        let mut state = self.state.lock().await;
+        let data = self.load(&mut state).await?;
+        match data.get(key) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 写入一个值
+    pub async fn insert<T: Serialize>(&self, key: &str, value: T) -> Result<(), SessionError> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.state.lock().await;
+        self.load(&mut state).await?.insert(key.to_string(), value);
+        state.dirty = true;
+        Ok(())
+    }
+
+    /// 删除一个值，返回被删除的值
+    pub async fn remove<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, SessionError> {
+        let mut state = self.state.lock().await;
+        let Some(value) = self.load(&mut state).await?.remove(key) else {
+            return Ok(None);
+        };
+        state.dirty = true;
+        Ok(Some(serde_json::from_value(value)?))
+    }
+
+    /// 当前的 Session ID，新 Session 在保存之前没有 ID
+    pub async fn id(&self) -> Option<String> {
+        self.state.lock().await.id.clone()
+    }
+
+    /// 更换 Session ID，保留数据并使旧的 ID 失效，返回新的 ID
+    ///
+    /// 登录、提权等权限变化时调用，防止会话固定攻击。
+    pub async fn regenerate(&self) -> Result<String, SessionError> {
+        let mut state = self.state.lock().await;
+        self.load(&mut state).await?;
+        if let Some(old_id) = state.id.take() {
+            self.store.delete(&old_id).await?;
+        }
+        let id = Uuid::new_v4().to_string();
+        state.id = Some(id.clone());
+        state.new_id = true;
+        state.dirty = true;
+        state.destroyed = false;
+        Ok(id)
+    }
+
+    /// 销毁 Session，删除存储中的数据，并在响应中让客户端删除 Cookie
+    pub async fn destroy(&self) -> Result<(), SessionError> {
+        let mut state = self.state.lock().await;
+        if let Some(id) = state.id.take() {
+            self.store.delete(&id).await?;
+        }
+        state.data = Some(SessionData::new());
+        state.dirty = false;
+        state.new_id = false;
+        state.destroyed = true;
+        Ok(())
+    }
+
+    // 按需加载；客户端带来的 ID 已失效时丢弃它，保存时生成新 ID，避免会话固定攻击
+    async fn load<'a>(
+        &self,
+        state: &'a mut SessionState,
+    ) -> Result<&'a mut SessionData, SessionError> {
+        if state.data.is_none() {
+            let data = match &state.id {
+                Some(id) => self.store.load(id).await?,
+                None => None,
+            };
+            if data.is_none() {
+                state.id = None;
+            }
+            state.data = Some(data.unwrap_or_default());
+        }
+        Ok(state.data.get_or_insert_with(SessionData::new))
+    }
+
+    // 响应前调用：保存修改过的 Session，或为已加载的 Session 续期
+    async fn commit(&self, ttl: u64) -> Result<CookieUpdate, SessionError> {
+        let mut state = self.state.lock().await;
+        let Some(data) = &state.data else {
+            return Ok(CookieUpdate::Keep);
+        };
+        if state.dirty {
+            let (id, new_id) = match &state.id {
+                Some(id) => (id.clone(), state.new_id),
+                None => (Uuid::new_v4().to_string(), true),
+            };
+            self.store.save(&id, data, ttl).await?;
+            state.id = Some(id.clone());
+            state.dirty = false;
+            state.new_id = false;
+            return Ok(if new_id {
+                CookieUpdate::Set(id)
+            } else {
+                CookieUpdate::Keep
+            });
+        }
+        if state.destroyed {
+            return Ok(CookieUpdate::Expire);
+        }
+        if let Some(id) = &state.id {
+            self.store.touch(id, ttl).await?;
+        }
+        Ok(CookieUpdate::Keep)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Session
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Session>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Session 不可用，请检查是否添加了 SessionLayer",
+        ))
+    }
+}
+
+/// Session 中间件
+///
+/// 从 Cookie 中读取 Session ID，为每个请求创建 [`Session`]，
+/// 并在响应时保存或续期；新建的 Session 会通过 `Set-Cookie` 下发 ID。
+/// Cookie 的安全属性默认来自全局的 [`CookiePolicy`]。
+#[derive(Clone)]
+pub struct SessionLayer {
+    store: Arc<dyn SessionStore>,
+    cookie_name: String,
+    ttl: u64,
+    policy: CookiePolicy,
+}
+
+impl SessionLayer {
+    pub fn new(store: impl SessionStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+            ttl: DEFAULT_TTL_SECS,
+            policy: CookiePolicy::global().clone(),
+        }
+    }
+
+    /// 设置保存 Session ID 的 Cookie 名称
+    pub fn with_cookie_name(mut self, cookie_name: &str) -> Self {
+        self.cookie_name = cookie_name.to_string();
+        self
+    }
+
+    /// 设置 Session 有效期（秒）
+    pub fn with_ttl(mut self, ttl: u64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// 设置 Cookie 的安全属性
+    pub fn with_cookie_policy(mut self, policy: CookiePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    // 从 Cookie 中读取 Session ID
+    fn session_id(&self, jar: &CookieJar) -> Option<String> {
+        jar.get_all(&self.cookie_name)
+            .find(|value| !value.is_empty())
+            .map(str::to_string)
+    }
+
+    // 有效期由服务端的 TTL 控制并随访问续期，所以不使用策略中的 `max_age`，
+    // 否则 Cookie 会在 Session 仍然有效时过期
+    fn cookie(&self, id: String) -> Cookie<'static> {
+        let mut cookie = self.policy.build(&self.cookie_name, id);
+        cookie.set_max_age(None);
+        cookie
+    }
+
+    // 让客户端删除 Cookie：空值 + `Max-Age=0`
+    fn removal_cookie(&self) -> Cookie<'static> {
+        let mut cookie = self.cookie(String::new());
+        cookie.make_removal();
+        cookie
+    }
+}
+
+impl<S> Layer<S> for SessionLayer {
+    type Service = SessionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// [`SessionLayer`] 生成的服务
+#[derive(Clone)]
+pub struct SessionService<S> {
+    inner: S,
+    layer: SessionLayer,
+}
+
+impl<S> Service<Request> for SessionService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        // 使用已经 ready 的 inner，把克隆留给下一次调用
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let jar = match CookieJar::from_headers(req.headers()) {
+                Ok(jar) => jar,
+                Err(err) => return Ok(err.into_response()),
+            };
+            let session_id = layer.session_id(&jar);
+            let had_cookie = session_id.is_some();
+            let session = Session::new(session_id, layer.store.clone());
+            req.extensions_mut().insert(session.clone());
+
+            let mut response = inner.call(req).await?;
+
+            let cookie = match session.commit(layer.ttl).await {
+                Ok(CookieUpdate::Set(id)) => layer.cookie(id),
+                Ok(CookieUpdate::Expire) if had_cookie => layer.removal_cookie(),
+                Ok(_) => return Ok(response),
+                Err(err) => return Ok(err.into_response()),
+            };
+            if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                response.headers_mut().append(header::SET_COOKIE, value);
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    // 记录调用次数的内存存储
+    #[derive(Clone, Default)]
+    struct CountingStore {
+        inner: MemorySessionStore,
+        loads: Arc<AtomicUsize>,
+        saves: Arc<AtomicUsize>,
+        touches: Arc<AtomicUsize>,
+    }
+
+    impl CountingStore {
+        fn loads(&self) -> usize {
+            self.loads.load(Ordering::SeqCst)
+        }
+
+        fn saves(&self) -> usize {
+            self.saves.load(Ordering::SeqCst)
+        }
+
+        fn touches(&self) -> usize {
+            self.touches.load(Ordering::SeqCst)
+        }
+
+        // 存入一个已登录的 Session
+        async fn seed(&self, id: &str, ttl: u64) {
+            let mut data = SessionData::new();
+            data.insert("user".to_string(), json!("alice"));
+            self.inner.save(id, &data, ttl).await.unwrap();
+        }
+
+        fn session(&self, id: Option<&str>) -> Session {
+            Session::new(id.map(str::to_string), Arc::new(self.clone()))
+        }
+    }
+
+    #[async_trait]
+    impl SessionStore for CountingStore {
+        async fn load(&self, id: &str) -> Result<Option<SessionData>, SessionError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            self.inner.load(id).await
+        }
+
+        async fn save(&self, id: &str, data: &SessionData, ttl: u64) -> Result<(), SessionError> {
+            self.saves.fetch_add(1, Ordering::SeqCst);
+            self.inner.save(id, data, ttl).await
+        }
+
+        async fn touch(&self, id: &str, ttl: u64) -> Result<(), SessionError> {
+            self.touches.fetch_add(1, Ordering::SeqCst);
+            self.inner.touch(id, ttl).await
+        }
+
+        async fn delete(&self, id: &str) -> Result<(), SessionError> {
+            self.inner.delete(id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_on_first_access_only() {
+        let store = CountingStore::default();
+        store.seed("s1", 60).await;
+        let session = store.session(Some("s1"));
+        assert_eq!(session.id().await.as_deref(), Some("s1"));
+        assert_eq!(store.loads(), 0);
+
+        let user: Option<String> = session.get("user").await.unwrap();
+        assert_eq!(user.as_deref(), Some("alice"));
+        let _: Option<String> = session.get("user").await.unwrap();
+        assert_eq!(store.loads(), 1);
+    }
+
+    #[tokio::test]
+    async fn unused_session_is_not_loaded_or_saved() {
+        let store = CountingStore::default();
+        store.seed("s1", 60).await;
+        let session = store.session(Some("s1"));
+
+        let update = session.commit(60).await.unwrap();
+        assert!(matches!(update, CookieUpdate::Keep));
+        assert_eq!((store.loads(), store.saves(), store.touches()), (0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn saves_only_when_modified() {
+        let store = CountingStore::default();
+        store.seed("s1", 60).await;
+
+        let session = store.session(Some("s1"));
+        let _: Option<String> = session.get("user").await.unwrap();
+        session.commit(60).await.unwrap();
+        assert_eq!((store.saves(), store.touches()), (0, 1));
+
+        let session = store.session(Some("s1"));
+        session.insert("theme", "dark").await.unwrap();
+        let update = session.commit(60).await.unwrap();
+        assert!(matches!(update, CookieUpdate::Keep));
+        assert_eq!((store.saves(), store.touches()), (1, 1));
+        let data = store.inner.load("s1").await.unwrap().unwrap();
+        assert_eq!(data["theme"], json!("dark"));
+
+        // 删除不存在的值不算修改
+        let session = store.session(Some("s1"));
+        let removed: Option<String> = session.remove("missing").await.unwrap();
+        assert!(removed.is_none());
+        session.commit(60).await.unwrap();
+        assert_eq!(store.saves(), 1);
+    }
+
+    #[tokio::test]
+    async fn new_session_gets_id_when_saved() {
+        let store = CountingStore::default();
+        let session = store.session(None);
+        session.insert("user", "alice").await.unwrap();
+        assert!(session.id().await.is_none());
+
+        let CookieUpdate::Set(id) = session.commit(60).await.unwrap() else {
+            panic!("new session must set a cookie");
+        };
+        assert_eq!(session.id().await.as_deref(), Some(id.as_str()));
+        assert!(store.inner.load(&id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn unknown_id_is_replaced_on_save() {
+        let store = CountingStore::default();
+        let session = store.session(Some("forged"));
+        session.insert("user", "alice").await.unwrap();
+
+        let CookieUpdate::Set(id) = session.commit(60).await.unwrap() else {
+            panic!("unknown id must be replaced");
+        };
+        assert_ne!(id, "forged");
+        assert!(store.inner.load("forged").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn access_renews_ttl() {
+        let store = CountingStore::default();
+        store.seed("s1", 60).await;
+        let session = store.session(Some("s1"));
+        let _: Option<String> = session.get("user").await.unwrap();
+        session.commit(3600).await.unwrap();
+
+        let expires_at = store.inner.sessions.lock().unwrap()["s1"].1;
+        assert!(expires_at > Instant::now() + Duration::from_secs(3000));
+    }
+
+    #[tokio::test]
+    async fn expired_session_is_not_loaded() {
+        let store = MemorySessionStore::new();
+        store.save("s1", &SessionData::new(), 0).await.unwrap();
+        assert!(store.load("s1").await.unwrap().is_none());
+        assert!(store.sessions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn regenerate_keeps_data_under_new_id() {
+        let store = CountingStore::default();
+        store.seed("s1", 60).await;
+        let session = store.session(Some("s1"));
+
+        let id = session.regenerate().await.unwrap();
+        assert_ne!(id, "s1");
+        assert!(store.inner.load("s1").await.unwrap().is_none());
+
+        let update = session.commit(60).await.unwrap();
+        assert!(matches!(update, CookieUpdate::Set(ref set) if *set == id));
+        let data = store.inner.load(&id).await.unwrap().unwrap();
+        assert_eq!(data["user"], json!("alice"));
+    }
+
+    #[tokio::test]
+    async fn destroy_deletes_session_and_expires_cookie() {
+        let store = CountingStore::default();
+        store.seed("s1", 60).await;
+        let session = store.session(Some("s1"));
+
+        session.destroy().await.unwrap();
+        assert!(session.id().await.is_none());
+        assert!(store.inner.load("s1").await.unwrap().is_none());
+
+        let update = session.commit(60).await.unwrap();
+        assert!(matches!(update, CookieUpdate::Expire));
+        assert_eq!(store.saves(), 0);
+    }
+}
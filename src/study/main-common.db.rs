@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tower_http::trace::TraceLayer;
 
+mod config;
 mod db;
 mod logger;
 
@@ -22,14 +23,14 @@ pub async fn health_checker_handler() -> impl IntoResponse {
 #[tokio::main]
 async fn main() {
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
 
     let routes = Router::new()
         .route("/check", get(health_checker_handler))
         .with_state(db::init_db().await)
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
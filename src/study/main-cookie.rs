@@ -1,16 +1,31 @@
 #![allow(unused)]
 use axum::{
+    extract::State,
     http::{header, HeaderMap, StatusCode},
-    response::Html,
+    response::{Html, IntoResponse, Response},
     routing::{get, get_service, post},
     Form, Router,
 };
 use serde::Deserialize;
+use sqlx::{Pool, Postgres};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 
 use tower_cookies::{Cookie, CookieManagerLayer, Cookies};
 
+mod config;
+mod cookie_jar;
+mod db;
+mod error;
+mod logger;
+mod secure_cookie;
+mod users;
+
+use cookie_jar::CookieJar;
+use secure_cookie::SecureCookies;
+
 const COOKIE_NAME: &'static str = "username";
+/// 访问计数，签名后客户端可以看到但无法修改
+const COUNTER_COOKIE_NAME: &str = "visited";
 
 #[derive(Deserialize)]
 pub struct UserLoginForm {
@@ -19,43 +34,11 @@ pub struct UserLoginForm {
 }
 
 /// 用户中心首页
-async fn user_center(headers: HeaderMap) -> Result<Html<String>, &'static str> {
-    //TODO 从 HTTP 头部中获取 Cookie 字段的值，并将其转换为字符串，如果获取失败或者头部中没有 Cookie 字段，则返回一个空字符串。
-    let cookies = headers
-        .get(header::COOKIE)
-        .and_then(|v| v.to_str().ok())
-        .map(|v| v.to_string())
-        .unwrap_or("".to_string()); // 从请求头获取所有COOKIE
-    if cookies.is_empty() {
-        return Err("NO COOKIE SETTEE"); // 没有 Cookie
-    }
-
-    let mut login_username: Option<String> = None;
-    let cookies: Vec<&str> = cookies.split(';').collect(); // 多个cookie用;分割
-    for cookie in cookies {
-        // 每个cookie都是用=分割的键值对
-        let cookie_pair: Vec<&str> = cookie.split('=').collect();
-        // 去除字符串前后多余空格
-        let cookie_name = cookie_pair[0].trim();
-        let cookie_value = cookie_pair[1].trim();
-        println!("cookie_name：{}", cookie_name);
-        println!("cookie_value：{}", cookie_value);
-        // 如果 cookie 的名称是我们希望的，并且值不为空
-        println!(
-            "{},{}",
-            cookie_name == COOKIE_NAME,
-            !cookie_value.is_empty()
-        );
-        if cookie_name == COOKIE_NAME && !cookie_value.is_empty() {
-            // 设置已登录用户的用户名
-            login_username = Some(String::from(cookie_value));
-            break;
-        }
-    }
-    println!("login_username：{:?}", login_username);
-    if login_username.is_none() {
+async fn user_center(cookies: SecureCookies) -> Result<Html<String>, &'static str> {
+    // 用户名保存在加密 Cookie 中，被篡改或伪造时解密失败
+    let Some(login_username) = cookies.get_private(COOKIE_NAME) else {
         return Err("COOKIE IS EMPTY"); // 没有我们需要的cookie
-    }
+    };
     let html = format!(
         r#"
         <!DOCTYPE html>
@@ -72,7 +55,7 @@ async fn user_center(headers: HeaderMap) -> Result<Html<String>, &'static str> {
           </body>
           </html>
         "#,
-        login_username.unwrap()
+        login_username
     );
     Ok(Html(html))
 }
@@ -110,67 +93,81 @@ async fn user_login() -> Html<String> {
     Html(html)
 }
 /// 用户登录
-async fn user_login_action(Form(frm): Form<UserLoginForm>) -> (StatusCode, HeaderMap, ()) {
+async fn user_login_action(
+    State(db): State<Pool<Postgres>>,
+    cookies: SecureCookies,
+    Form(frm): Form<UserLoginForm>,
+) -> Result<(StatusCode, HeaderMap, ()), Response> {
     let mut headers = HeaderMap::new();
-    if !(&frm.username == "axum.rs" && &frm.password == "axum.rs") {
+    let user = users::authenticate(&db, &frm.username, &frm.password)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    if let Some(user) = user {
+        cookies.add_private(COOKIE_NAME, user.username); // 设置加密的Cookie
+        headers.insert(axum::http::header::LOCATION, "/".parse().unwrap()); // 跳转到用户中心首页
+    } else {
         headers.insert(
             axum::http::header::LOCATION,
             "/login?msg=用户名或密码错误".parse().unwrap(),
         ); // 跳转到登录页面
-    } else {
-        let cookie = format!("{}={}", COOKIE_NAME, frm.username);
-        headers.insert(
-            axum::http::header::SET_COOKIE,
-            cookie.as_str().parse().unwrap(),
-        ); // 设置Cookie
-        headers.insert(axum::http::header::LOCATION, "/".parse().unwrap()); // 跳转到用户中心首页
     }
-    (StatusCode::FOUND, headers, ())
+    Ok((StatusCode::FOUND, headers, ()))
 }
 /// 退出登录
-async fn user_logout() -> (StatusCode, HeaderMap, ()) {
-    let cookie = format!("{}=", COOKIE_NAME);
+async fn user_logout(cookies: SecureCookies) -> (StatusCode, HeaderMap, ()) {
+    cookies.remove(COOKIE_NAME); // 删除Cookie
     let mut headers = HeaderMap::new();
-    headers.insert(
-        axum::http::header::SET_COOKIE,
-        cookie.as_str().parse().unwrap(),
-    ); // 清空Cookie
     headers.insert(axum::http::header::LOCATION, "/login".parse().unwrap()); // 跳转到登录页面
     (StatusCode::FOUND, headers, ())
 }
 
 // 获取并处理cookie
-async fn handler(cookies: Cookies) -> String {
+async fn handler(cookies: SecureCookies) -> String {
     let visited = cookies
-        .get(COOKIE_NAME)
-        .and_then(|c| c.value().parse().ok())
+        .get_signed(COUNTER_COOKIE_NAME)
+        .and_then(|v| v.parse().ok())
         .unwrap_or(0);
     println!("{}", visited);
     if visited > 10 {
-        cookies.remove(Cookie::new(COOKIE_NAME, ""));
+        cookies.remove(COUNTER_COOKIE_NAME);
         "Counter has been reset".into()
     } else {
-        cookies.add(Cookie::new(COOKIE_NAME, (visited + 1).to_string()));
+        cookies.add_signed(COUNTER_COOKIE_NAME, (visited + 1).to_string());
         format!("You've been here {} times before", visited)
     }
 }
 
+// 查看请求中的所有 Cookie，同名的按出现顺序列出
+async fn list_cookies(jar: CookieJar) -> axum::Json<serde_json::Value> {
+    let cookies: Vec<_> = jar
+        .iter()
+        .map(|(name, value)| serde_json::json!({"name": name, "value": value}))
+        .collect();
+    axum::Json(serde_json::json!({"status": "success", "data": cookies}))
+}
+
 #[tokio::main]
 async fn main() {
-    if std::env::var_os("RUST_LOG").is_none() {
-        std::env::set_var("RUST_LOG", "tower_http=debug,middleware=debug");
-    }
-    tracing_subscriber::fmt::init();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
+
+    // 启动时加载 Cookie 密钥，配置无效时直接退出
+    secure_cookie::CookieKeys::global();
+
+    let db = db::connect().await;
 
     let routes = Router::new()
         .route("/", get(user_center))
         .route("/cook", get(handler))
+        .route("/cookies", get(list_cookies))
         .route("/login", get(user_login).post(user_login_action))
         .route("/logout", get(user_logout))
+        // 注册、登录和修改密码的 JSON 接口
+        .nest("/users", users::router())
+        .with_state(db)
         .layer(CookieManagerLayer::new())
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
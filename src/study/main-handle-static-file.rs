@@ -7,12 +7,12 @@ use axum::{
 };
 use tower_http::{services::ServeDir, trace::TraceLayer};
 
+mod config;
+mod logger;
+
 #[tokio::main]
 async fn main() {
-    if std::env::var_os("RUST_LOG").is_none() {
-        std::env::set_var("RUST_LOG", "tower_http=debug,middleware=debug");
-    }
-    tracing_subscriber::fmt::init();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
 
     let routes = Router::new()
         .route("/static/axum-rs.txt", get(axum_rs_txt))
@@ -22,7 +22,7 @@ async fn main() {
         )
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
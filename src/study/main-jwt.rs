@@ -3,39 +3,118 @@
 use std::fmt::Display;
 
 use axum::{
-    async_trait,
-    extract::{FromRequestParts, Query},
-    http::{request::Parts, HeaderMap, StatusCode},
+    extract::{FromRef, Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::{Html, IntoResponse, Response},
     routing::{get, post},
-    Extension, Form, Json, RequestPartsExt, Router,
+    Extension, Form, Json, Router,
 };
-use axum_extra::TypedHeader;
-use headers::{authorization::Bearer, Authorization};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::jwk::JwkSet;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sqlx::{Pool, Postgres};
 use tower_cookies::{Cookie, CookieManagerLayer, Cookies};
 use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 
+mod authz;
+mod config;
+mod cookie_jar;
+mod db;
+mod error;
+mod jwt;
 mod logger;
 mod redis_client;
+mod secure_cookie;
+mod session;
+mod users;
 
-const JWT_SECRET: &str = "https://AXUM.RS";
+use authz::{Admin, RequireRole, RequireScope, Scope};
+use jwt::{AccessClaims, AuthError, Keys, TokenStore};
+
+/// 访问令牌中的自定义声明
+#[derive(Debug, Serialize, Deserialize)]
+struct CustomClaims {
+    company: String,
+    /// 角色和权限范围
+    #[serde(flatten)]
+    access: AccessClaims,
+}
+
+/// 读取个人资料的权限
+struct ProfileRead;
+
+impl Scope for ProfileRead {
+    const NAME: &'static str = "profile:read";
+}
+
+type Claims = jwt::Claims<CustomClaims>;
+
+/// 应用状态
+#[derive(Clone)]
+struct AppState {
+    db: Pool<Postgres>,
+    tokens: TokenStore,
+}
+
+impl FromRef<AppState> for Pool<Postgres> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for TokenStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.tokens.clone()
+    }
+}
 
 #[tokio::main]
 async fn main() {
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
+
+    // 启动时加载 JWT 密钥，密钥文件无效时直接退出
+    Keys::global();
+
+    let db = db::connect().await;
+    let redis = match redis_client::RedisPool::from_config().await {
+        Ok(redis) => redis,
+        Err(err) => {
+            println!("🔥 Failed to connect to Redis: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let state = AppState {
+        db: db.clone(),
+        tokens: TokenStore::from_config(redis),
+    };
+
+    // 需要访问令牌的路由；签发、刷新令牌和公钥不经过校验，持有过期令牌的客户端仍可刷新
+    let protected_routes = Router::new()
+        .route("/token/revoke", post(revoke))
+        .route("/protected", get(protected))
+        .route("/profile", get(profile))
+        .route("/admin", get(admin))
+        // 校验访问令牌，把身份交给授权提取器
+        .route_layer(middleware::from_fn_with_state(
+            state.tokens.clone(),
+            jwt::authenticate,
+        ));
 
     let routes = Router::new()
         .route("/authorize", post(authorize))
-        .route("/protected", get(protected))
+        .route("/token/refresh", post(refresh))
+        .route("/.well-known/jwks.json", get(jwks))
+        .merge(protected_routes)
+        // 注册、登录和修改密码的 JSON 接口
+        .nest("/users", users::router().with_state(db))
+        .with_state(state)
         .layer(CookieManagerLayer::new())
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
@@ -44,96 +123,136 @@ async fn main() {
 
 async fn protected(claims: Claims) -> Result<String, AuthError> {
     Ok(format!(
-        "Welcome to the protected area:\nYour data:\n{claims}",
+        "Welcome to the protected area:\nYour data:\nEmail: {}\nCompany: {}\nRoles: {}\nScope: {}",
+        claims.sub,
+        claims.custom.company,
+        claims.custom.access.roles.join(","),
+        claims.custom.access.scope,
     ))
 }
 
-async fn authorize(Json(payload): Json<AuthPayload>) -> Result<Json<AuthBody>, AuthError> {
-    if payload.client_id.is_empty() || payload.client_secret.is_empty() {
-        return Err(AuthError::MissingCredentials);
-    }
-
-    if payload.client_id != "axum.rs" || payload.client_secret != "team@axum.rs" {
-        return Err(AuthError::WrongCredentials);
-    }
-    let claims = Claims {
-        sub: "team@axum.rs".to_owned(),
-        company: "AXUM.RS".to_owned(),
-        exp: 2000000000,
-    };
-
-    let token = encode(&Header::default(), &claims, &Keys::global().encoding)
-        .map_err(|_| AuthError::TokenCreation)?;
-
-    Ok(Json(AuthBody::new(token)))
+/// 需要 `profile:read` 权限范围
+async fn profile(RequireScope { principal, .. }: RequireScope<ProfileRead>) -> String {
+    format!("Profile of {}", principal.subject)
 }
 
-pub struct Keys {
-    encoding: EncodingKey,
-    decoding: DecodingKey,
+/// 只有管理员可以访问
+async fn admin(RequireRole { principal, .. }: RequireRole<Admin>) -> String {
+    format!("Welcome, administrator {}", principal.subject)
 }
 
-impl Keys {
-    pub fn new(secret: &[u8]) -> Self {
-        Self {
-            encoding: EncodingKey::from_secret(secret),
-            decoding: DecodingKey::from_secret(secret),
-        }
+async fn authorize(
+    State(db): State<Pool<Postgres>>,
+    State(tokens): State<TokenStore>,
+    Json(payload): Json<AuthPayload>,
+) -> Result<Json<AuthBody>, AuthError> {
+    if payload.client_id.is_empty() || payload.client_secret.is_empty() {
+        return Err(AuthError::MissingCredentials);
     }
 
-    pub fn global() -> Self {
-        Self::new(JWT_SECRET.as_bytes())
-    }
+    // client_id 为用户名，client_secret 为密码
+    let user = users::authenticate(&db, &payload.client_id, &payload.client_secret)
+        .await
+        .map_err(|err| {
+            tracing::error!("failed to authenticate {}: {}", payload.client_id, err);
+            AuthError::Internal
+        })?
+        .ok_or(AuthError::WrongCredentials)?;
+
+    // 只授予用户角色允许的权限范围
+    let scope = authz::grant_scopes(&user.roles, payload.scope.as_deref()).join(" ");
+    let refresh_token = tokens.issue_refresh(&user.email, &scope).await?;
+    Ok(Json(issue_tokens(&tokens, user, scope, refresh_token)?))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    company: String,
-    exp: usize,
+/// 用刷新令牌换发新的访问令牌和刷新令牌
+async fn refresh(
+    State(db): State<Pool<Postgres>>,
+    State(tokens): State<TokenStore>,
+    Json(payload): Json<RefreshPayload>,
+) -> Result<Json<AuthBody>, AuthError> {
+    let grant = tokens.rotate_refresh(&payload.refresh_token).await?;
+    // 重新读取用户，角色变化后新的访问令牌随之生效
+    let user = users::find_by_email(&db, &grant.sub)
+        .await
+        .map_err(|err| {
+            tracing::error!("failed to load user {}: {}", grant.sub, err);
+            AuthError::Internal
+        })?
+        .ok_or(AuthError::InvalidRefreshToken)?;
+    let scope = authz::grant_scopes(&user.roles, Some(&grant.scope)).join(" ");
+    Ok(Json(issue_tokens(
+        &tokens,
+        user,
+        scope,
+        grant.refresh_token,
+    )?))
 }
 
-impl Display for Claims {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Email: {}\nCompany: {}", self.sub, self.company)
+/// 吊销当前的访问令牌；同时提供自己的刷新令牌时，吊销它所在的整个家族
+async fn revoke(
+    State(tokens): State<TokenStore>,
+    claims: Claims,
+    payload: Option<Json<RefreshPayload>>,
+) -> Result<StatusCode, AuthError> {
+    tokens.revoke_access(&claims.jti, claims.exp).await?;
+    if let Some(Json(payload)) = payload {
+        tokens
+            .revoke_refresh(&claims.sub, &payload.refresh_token)
+            .await?;
     }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-#[async_trait]
-impl<S> FromRequestParts<S> for Claims
-where
-    S: Send + Sync,
-{
-    type Rejection = AuthError;
+/// 公开的签名公钥，其它服务用它校验令牌
+async fn jwks() -> Json<JwkSet> {
+    Json(Keys::global().jwks())
+}
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| AuthError::InvalidToken)?;
+// 签发访问令牌，和刷新令牌一起返回
+fn issue_tokens(
+    tokens: &TokenStore,
+    user: users::User,
+    scope: String,
+    refresh_token: String,
+) -> Result<AuthBody, AuthError> {
+    let claims = tokens.access_claims(
+        user.email,
+        CustomClaims {
+            company: "AXUM.RS".to_owned(),
+            access: AccessClaims {
+                roles: user.roles,
+                scope,
+            },
+        },
+    );
 
-        let token_data = decode::<Claims>(
-            bearer.token(),
-            &Keys::global().decoding,
-            &Validation::default(),
-        )
-        .map_err(|_| AuthError::InvalidToken)?;
+    let token = Keys::global()
+        .encode(&claims)
+        .map_err(|_| AuthError::TokenCreation)?;
 
-        Ok(token_data.claims)
-    }
+    Ok(AuthBody::new(
+        token,
+        tokens.access_ttl_secs(),
+        refresh_token,
+    ))
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthBody {
     pub access_token: String,
     pub token_type: String,
+    pub expires_in: u64,
+    pub refresh_token: String,
 }
 
 impl AuthBody {
-    pub fn new(access_token: String) -> Self {
+    pub fn new(access_token: String, expires_in: u64, refresh_token: String) -> Self {
         Self {
             access_token,
             token_type: "Bearer".to_string(),
+            expires_in,
+            refresh_token,
         }
     }
 }
@@ -142,27 +261,11 @@ impl AuthBody {
 struct AuthPayload {
     client_id: String,
     client_secret: String,
+    /// 申请的权限范围，空格分隔；不提供时授予角色允许的全部范围
+    scope: Option<String>,
 }
 
-#[derive(Debug)]
-enum AuthError {
-    WrongCredentials,
-    MissingCredentials,
-    TokenCreation,
-    InvalidToken,
-}
-
-impl IntoResponse for AuthError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AuthError::WrongCredentials => (StatusCode::UNAUTHORIZED, "Wrong credentials"),
-            AuthError::MissingCredentials => (StatusCode::BAD_REQUEST, "Missing credentials"),
-            AuthError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"),
-            AuthError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
-        };
-        let body = Json(json!({
-            "error": error_message,
-        }));
-        (status, body).into_response()
-    }
+#[derive(Debug, Deserialize)]
+struct RefreshPayload {
+    refresh_token: String,
 }
@@ -12,6 +12,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tower_http::add_extension::AddExtensionLayer;
 
+mod config;
+
 #[derive(Clone)]
 pub struct UserInfo {
     pub username: String,
@@ -70,7 +72,7 @@ async fn main() {
             rdb: redis_client_arc,
         })));
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
@@ -1,63 +1,127 @@
 #![allow(unused)]
 use axum::{
-    async_trait,
-    extract::{extractor_middleware, FromRequest},
+    extract::{FromRef, State},
     http::StatusCode,
+    middleware,
+    response::IntoResponse,
     routing::{get, post},
-    Router,
+    Json, Router,
 };
-use axum_extra::middleware;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
 use tower_http::trace::TraceLayer;
 
-struct RequireAuth;
-
-#[async_trait]
-impl<B> FromRequest<B> for RequireAuth
-where
-    B: Send,
-{
-    type Rejection = StatusCode;
-
-    async fn from_request(req: &mut FromRequestParts<B>) -> Result<Self, Self::Rejection> {
-        let auth_header = req
-            .headers()
-            .and_then(|headers| headers.get(http::header::AUTHORIZATION))
-            .and_then(|value| value.to_str().ok());
-
-        if let Some(value) = auth_header {
-            if value == "secret" {
-                return Ok(Self);
-            }
-        }
+mod api_keys;
+mod auth;
+mod authz;
+mod config;
+mod cookie_jar;
+mod db;
+mod error;
+mod jwt;
+mod logger;
+mod redis_client;
+mod secure_cookie;
+mod session;
+mod users;
 
-        Err(StatusCode::UNAUTHORIZED)
-    }
-}
+use auth::Authenticator;
+use authz::{Permission, Principal};
+use error::AppError;
+use jwt::{Keys, TokenStore};
+use redis_client::RedisPool;
+use session::{RedisSessionStore, Session, SessionLayer};
 
 #[tokio::main]
 async fn main() {
-    if std::env::var_os("RUST_LOG").is_none() {
-        std::env::set_var("RUST_LOG", "tower_http=debug,middleware=debug");
-    }
-    tracing_subscriber::fmt::init();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
+
+    let db = db::connect().await;
+    let redis = match RedisPool::from_config().await {
+        Ok(redis) => redis,
+        Err(err) => {
+            println!("🔥 Failed to connect to redis: {}", err);
+            std::process::exit(1);
+        }
+    };
+    // 启动时加载签名密钥，配置错误时尽早退出
+    Keys::global();
+
+    // API Key、Bearer 访问令牌和 Session 都可以用来认证
+    let authenticator = Authenticator::new(db.clone(), TokenStore::from_config(redis.clone()));
+    let require_auth = middleware::from_fn_with_state(authenticator, auth::require_auth);
 
     let routes = Router::new()
-        .route("/foo", get(foo))
-        .route("/bar", get(bar))
-        .layer(TraceLayer::new_for_http())
-        .route_layer(extractor_middleware::<RequireAuth>());
+        .route("/", get(index))
+        // 只需要登录
+        .route("/foo", get(foo).route_layer(require_auth.clone()))
+        // 需要登录且具有管理员角色；后加的 layer 先执行，所以先认证再检查角色
+        .route(
+            "/bar",
+            get(bar)
+                .route_layer(middleware::from_fn_with_state(
+                    Permission::role("admin"),
+                    authz::guard,
+                ))
+                .route_layer(require_auth.clone()),
+        )
+        .route("/login", post(login).delete(logout))
+        .nest("/api-keys", api_keys::router().route_layer(require_auth))
+        // 注册、登录和修改密码的 JSON 接口
+        .nest("/users", users::router())
+        .with_state(db)
+        .layer(SessionLayer::new(RedisSessionStore::new(redis)))
+        .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
     axum::serve(listener, routes).await.unwrap();
 }
 
-async fn foo() -> &'static str {
-    "Welcome to axum.rs"
+async fn index() -> &'static str {
+    "Hello, axum.rs"
+}
+
+async fn foo(principal: Principal) -> String {
+    format!("Welcome to axum.rs, {}", principal.subject)
 }
 
-async fn bar() -> &'static str {
-    "Powered by axum.rs"
+async fn bar(principal: Principal) -> String {
+    format!("Powered by axum.rs, {}", principal.subject)
+}
+
+/// 用户名和密码登录，身份保存到 Session 中
+async fn login(
+    State(db): State<Pool<Postgres>>,
+    session: Session,
+    Json(body): Json<users::Login>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = users::authenticate(&db, &body.username, &body.password)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("用户名或密码错误".to_string()))?;
+    // 登录后更换 Session ID，防止会话固定攻击
+    session
+        .regenerate()
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    let principal = Principal {
+        subject: user.username,
+        roles: user.roles,
+        scopes: None,
+    };
+    session
+        .insert(authz::SESSION_KEY, &principal)
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    Ok(Json(json!({"status": "success", "data": principal})))
+}
+
+async fn logout(session: Session) -> Result<impl IntoResponse, AppError> {
+    session
+        .destroy()
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
 }
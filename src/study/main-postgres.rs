@@ -1,39 +1,120 @@
 #![allow(unused)]
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{MatchedPath, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Form, Json, Router,
 };
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_str, json, Value as JsonValue};
 use sqlx::{
     postgres::{PgPoolOptions, PgRow},
-    Pool, Postgres, Row,
+    FromRow, Pool, Postgres, QueryBuilder, Row,
 };
 use std::{env, fmt::Display, str::FromStr, sync::Arc};
 use tower_http::trace::TraceLayer;
 
+mod cache;
+mod config;
+mod db;
+mod error;
+mod idempotency;
 mod logger;
+mod redis_client;
+
+use cache::ReadThroughCache;
+use error::AppError;
+use redis_client::RedisPool;
+
+/// 分页时默认每页条数
+const DEFAULT_PAGE_SIZE: i64 = 20;
+/// 分页时每页最多条数
+const MAX_PAGE_SIZE: i64 = 100;
 
 pub struct AppState {
     db: Pool<Postgres>,
+    cache: ReadThroughCache,
 }
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, FromRow)]
 pub struct Account {
     pub id: i32,
     pub username: String,
     pub balance: i32,
 }
+/// 转账流水
+#[derive(Serialize, Debug)]
+pub struct Transfer {
+    pub id: i64,
+    pub from_id: i32,
+    pub to_id: i32,
+    pub amount: i32,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
 #[derive(Deserialize)]
 pub struct CreateAccount {
     pub username: String,
     pub balance: i32,
 }
+/// 修改账户，只修改提交了的字段
+#[derive(Deserialize)]
+pub struct UpdateAccount {
+    pub username: Option<String>,
+    pub balance: Option<i32>,
+}
+/// 发起转账
+#[derive(Deserialize)]
+pub struct CreateTransfer {
+    pub from_id: i32,
+    pub to_id: i32,
+    pub amount: i32,
+}
+/// 账户列表的查询参数
+#[derive(Deserialize)]
+pub struct ListAccounts {
+    /// 每页条数
+    pub limit: Option<i64>,
+    /// 游标：上一页返回的 `next_cursor`
+    pub after: Option<String>,
+    /// 按用户名前缀过滤
+    pub username_prefix: Option<String>,
+    /// 排序字段，`-` 前缀表示倒序，如 `-balance`
+    pub sort: Option<String>,
+}
+
+/// 分页游标，记录上一页最后一条记录的排序字段值和 id
+///
+/// 游标自带比较所需的值，即使那条记录已被删除也能继续翻页。
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    /// 生成游标时的排序方式，换了排序方式游标就不再有效
+    sort: String,
+    value: JsonValue,
+    id: i32,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+// 账户在缓存中的 key
+fn account_key(id: i32) -> String {
+    format!("account:{}", id)
+}
 
 pub async fn health_checker_handler() -> impl IntoResponse {
     const MESSAGE: &str = "Simple CRUD API with Rust, SQLX, Postgres,and Axum";
@@ -49,28 +130,101 @@ pub async fn health_checker_handler() -> impl IntoResponse {
 // 所有账户
 async fn list(
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<JsonValue>)> {
-    let notes = match sqlx::query_as!(
-        Account,
-        "SELECT id, username, balance FROM account ORDER BY id DESC",
-    )
-    .fetch_all(&data.db)
-    .await
-    {
-        Ok(result) => result,
-        Err(_) => {
-            let error_response = json!({
-                "status": "fail",
-                "message": "Something bad happened while fetching all note items",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+    Query(params): Query<ListAccounts>,
+) -> Result<impl IntoResponse, AppError> {
+    let sort = params.sort.as_deref().unwrap_or("-id");
+    let (column, descending) = match sort.strip_prefix('-') {
+        Some(column) => (column, true),
+        None => (sort, false),
+    };
+    // 排序字段只能从白名单中取，不能直接拼接用户输入
+    let column = match column {
+        "id" => "id",
+        "username" => "username",
+        "balance" => "balance",
+        _ => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported sort field: {}",
+                sort
+            )))
         }
     };
+    let (direction, comparison) = if descending {
+        ("DESC", "<")
+    } else {
+        ("ASC", ">")
+    };
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut query =
+        QueryBuilder::<Postgres>::new("SELECT id, username, balance FROM account WHERE TRUE");
+    if let Some(prefix) = params.username_prefix.as_deref().filter(|p| !p.is_empty()) {
+        let prefix = prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        query
+            .push(" AND username LIKE ")
+            .push_bind(format!("{}%", prefix));
+    }
+    if let Some(after) = params.after.as_deref() {
+        let invalid = || AppError::BadRequest(format!("Invalid cursor: {}", after));
+        let cursor = Cursor::decode(after)
+            .filter(|cursor| cursor.sort == sort)
+            .ok_or_else(invalid)?;
+        // keyset 分页：从游标记录的 (排序字段, id) 之后开始取
+        query.push(format!(" AND ({column}, id) {comparison} ("));
+        match column {
+            "username" => query.push_bind(cursor.value.as_str().ok_or_else(invalid)?.to_string()),
+            _ => query.push_bind(
+                cursor
+                    .value
+                    .as_i64()
+                    .and_then(|v| i32::try_from(v).ok())
+                    .ok_or_else(invalid)?,
+            ),
+        };
+        query.push(", ").push_bind(cursor.id).push(")");
+    }
+    query
+        .push(format!(
+            " ORDER BY {column} {direction}, id {direction} LIMIT "
+        ))
+        // 多取一条，用来判断是否还有下一页
+        .push_bind(limit + 1);
+
+    let mut notes = query
+        .build_query_as::<Account>()
+        .fetch_all(&data.db)
+        .await?;
+
+    let next_cursor = if notes.len() as i64 > limit {
+        notes.truncate(limit as usize);
+        notes.last().map(|account| {
+            let value = match column {
+                "username" => json!(account.username),
+                "balance" => json!(account.balance),
+                _ => json!(account.id),
+            };
+            Cursor {
+                sort: sort.to_string(),
+                value,
+                id: account.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
 
     let json_response = json!({
         "status": "success",
         "results": notes.len(),
-        "data": notes
+        "data": notes,
+        "next_cursor": next_cursor
     });
 
     Ok(Json(json_response))
@@ -80,125 +234,111 @@ async fn list(
 async fn insert_lists(
     State(data): State<Arc<AppState>>,
     Json(body): Json<CreateAccount>,
-) -> Result<impl IntoResponse, (StatusCode, Json<JsonValue>)> {
-    let query_result =
-        sqlx::query_as!(Account,
+) -> Result<impl IntoResponse, AppError> {
+    // 用户名重复时数据库返回 23505，由 AppError 转换为 409
+    let row = sqlx::query_as!(
+        Account,
         "INSERT INTO account (username, balance) VALUES ($1, $2) RETURNING id, username, balance",
         body.username,
         body.balance,
     )
-        .fetch_one(&data.db)
-        .await;
+    .fetch_one(&data.db)
+    .await?;
 
-    match query_result {
-        Ok(row) => {
-            let note_response = json!({"status": "success","data": json!({
-                "id": row.id,
-                "username": row.username,
-                "balance": row.balance,
-            })});
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LOCATION,
+        format!("/api/v1/accounts/{}", row.id).parse().unwrap(),
+    );
+    let note_response = json!({"status": "success","data": row});
 
-            return Ok((StatusCode::CREATED, Json(note_response)));
-        }
-        Err(e) => {
-            if e.to_string()
-                .contains("duplicate key value violates unique constraint")
-            {
-                let error_response = serde_json::json!({
-                    "status": "fail",
-                    "message": "Note with that title already exists",
-                });
-                return Err((StatusCode::CONFLICT, Json(error_response)));
-            }
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"status": "error","message": format!("{:?}", e)})),
-            ));
-        }
-    }
+    Ok((StatusCode::CREATED, headers, Json(note_response)))
 }
 
 async fn find(
     Path(id): Path<i32>,
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = sqlx::query!(
-        "SELECT id,username,balance FROM account WHERE id=$1 ORDER BY id DESC LIMIT 1",
+) -> Result<impl IntoResponse, AppError> {
+    let db = data.db.clone();
+    let row = data
+        .cache
+        .get_or_load(&account_key(id), move || async move {
+            sqlx::query_as!(
+                Account,
+                "SELECT id,username,balance FROM account WHERE id=$1",
+                id
+            )
+            .fetch_optional(&db)
+            .await
+        })
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Account with ID: {} not found", id)))?;
+
+    let note_response = json!({"status": "success","data": row});
+
+    Ok(Json(note_response))
+}
+
+// 修改账户
+async fn update_account(
+    data: &AppState,
+    id: i32,
+    body: UpdateAccount,
+) -> Result<Json<JsonValue>, AppError> {
+    let note = sqlx::query_as!(
+        Account,
+        "UPDATE account SET username = COALESCE($1, username), balance = COALESCE($2, balance) \
+         WHERE id = $3 RETURNING id, username, balance",
+        body.username,
+        body.balance,
         id
     )
-    .fetch_one(&data.db)
-    .await;
+    .fetch_optional(&data.db)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Account with ID: {} not found", id)))?;
+    data.cache.invalidate(&[account_key(id)]).await;
 
-    match query_result {
-        Ok(row) => {
-            let note_response = json!({"status": "success","data": json!({
-                "id": row.id,
-                "username": row.username,
-                "balance": row.balance,
-            })});
+    let note_response = json!({"status": "success","data": note});
 
-            return Ok(Json(note_response));
-        }
-        Err(_) => {
-            let error_response = json!({
-                "status": "fail",
-                "message": format!("Note with ID: {} not found", id)
-            });
-            return Err((StatusCode::NOT_FOUND, Json(error_response)));
-        }
-    }
+    Ok(Json(note_response))
 }
 
 async fn update(
     Path((id, balance)): Path<(i32, i32)>,
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = sqlx::query!(
-        "update account set balance=$1 where id=$2 returning id, username, balance",
-        balance,
-        id
-    )
-    .fetch_one(&data.db)
-    .await;
-
-    match query_result {
-        Ok(note) => {
-            let note_response = json!({"status": "success","data": json!({
-                "id": note.id,
-                "username": note.username,
-                "balance": note.balance,
-            })});
+) -> Result<impl IntoResponse, AppError> {
+    let body = UpdateAccount {
+        username: None,
+        balance: Some(balance),
+    };
+    update_account(&data, id, body).await
+}
 
-            return Ok(Json(note_response));
-        }
-        Err(err) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"status": "error","message": format!("{:?}", err)})),
-            ));
-        }
-    }
+async fn patch(
+    Path(id): Path<i32>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateAccount>,
+) -> Result<impl IntoResponse, AppError> {
+    update_account(&data, id, body).await
 }
 
 //
 async fn delete(
     Path(id): Path<i32>,
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     let rows_affected = sqlx::query!("DELETE FROM account WHERE id = $1", id)
         .execute(&data.db)
-        .await
-        .unwrap()
+        .await?
         .rows_affected();
 
     if rows_affected == 0 {
-        let error_response = json!({
-            "status": "fail",
-            "message": format!("Note with ID: {} not found", id)
-        });
-
-        return Err((StatusCode::NOT_FOUND, Json(error_response)));
+        return Err(AppError::NotFound(format!(
+            "Account with ID: {} not found",
+            id
+        )));
     }
+    data.cache.invalidate(&[account_key(id)]).await;
 
     let success_response = json!({
         "status": "success",
@@ -208,67 +348,152 @@ async fn delete(
     Ok(Json(success_response))
 }
 
-async fn transfer(
-    Path((from_id, to_id, balance)): Path<(i32, i32, i32)>,
-    State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+// 在一个事务中完成转账并写入流水
+async fn transfer_funds(
+    data: &AppState,
+    from_id: i32,
+    to_id: i32,
+    amount: i32,
+) -> Result<Transfer, AppError> {
+    if amount <= 0 || from_id == to_id {
+        return Err(AppError::BadRequest(
+            "转账金额必须大于0，且不能转给自己".to_string(),
+        ));
+    }
+
+    // 出账、入账和流水必须在同一个事务中完成；
+    // 任何一步出错时 tx 被 drop，事务会自动回滚
+    let mut tx = data.db.begin().await?;
+
+    // 按 id 顺序锁定双方账户，避免两个方向相反的转账互相等待造成死锁
+    let accounts = sqlx::query_as!(
+        Account,
+        "SELECT id, username, balance FROM account WHERE id = $1 OR id = $2 ORDER BY id FOR UPDATE",
+        from_id,
+        to_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let from = accounts.iter().find(|account| account.id == from_id);
+    let to = accounts.iter().find(|account| account.id == to_id);
+    let from = match (from, to) {
+        (Some(from), Some(_)) => from,
+        _ => {
+            tx.rollback().await?;
+            return Err(AppError::NotFound("转出或转入账户不存在".to_string()));
+        }
+    };
+
+    if from.balance < amount {
+        tx.rollback().await?;
+        return Err(AppError::UnprocessableEntity(format!(
+            "账户 {} 余额不足",
+            from.id
+        )));
+    }
+
     // 修改出账记录
-    let stmt_c = sqlx::query!(
-        "UPDATE account SET balance=balance-$1 WHERE id=$2 AND balance>=$1 returning id, username, balance",
-        balance,
+    sqlx::query!(
+        "UPDATE account SET balance=balance-$1 WHERE id=$2",
+        amount,
         from_id
     )
-    .fetch_one(&data.db)
-    .await;
+    .execute(&mut *tx)
+    .await?;
 
     // 修改入账记录
-    let stmt_r = sqlx::query!(
-        "UPDATE account SET balance=balance+$1 WHERE id=$2 returning id, username, balance",
-        balance,
+    sqlx::query!(
+        "UPDATE account SET balance=balance+$1 WHERE id=$2",
+        amount,
         to_id
     )
-    .fetch_one(&data.db)
-    .await;
-
-    if let (Ok(stmt_c), Ok(stmt_r)) = (stmt_c, stmt_r) {
-        let success_response = json!({
-            "status": "success",
-            "message": "转账成功",
-            "data":json!({
-                    "from":{
-                        "id": stmt_c.id,
-                        "username": stmt_c.username,
-                        "balance": stmt_c.balance
-                    },
-                    "to":{
-                        "id": stmt_r.id,
-                        "username": stmt_r.username,
-                        "balance": stmt_r.balance
-                    },
-            })
-        });
-
-        return Ok(Json(success_response));
-    } else {
-        let error_response = json!({
-            "status": "fail",
-            "message": "出账或入账记录更新失败！"
-        });
+    .execute(&mut *tx)
+    .await?;
+
+    // 写入转账流水
+    let ledger = sqlx::query_as!(
+        Transfer,
+        "INSERT INTO transfers (from_id, to_id, amount, status) VALUES ($1, $2, $3, 'completed') \
+         RETURNING id, from_id, to_id, amount, status, created_at",
+        from_id,
+        to_id,
+        amount
+    )
+    .fetch_one(&mut *tx)
+    .await?;
 
-        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
-    }
+    tx.commit().await?;
+    data.cache
+        .invalidate(&[account_key(from_id), account_key(to_id)])
+        .await;
+
+    Ok(ledger)
+}
+
+async fn transfer(
+    Path((from_id, to_id, amount)): Path<(i32, i32, i32)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let ledger = transfer_funds(&data, from_id, to_id, amount).await?;
+
+    let success_response = json!({
+        "status": "success",
+        "message": "转账成功",
+        "data": ledger
+    });
+
+    Ok(Json(success_response))
+}
+
+async fn create_transfer(
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateTransfer>,
+) -> Result<impl IntoResponse, AppError> {
+    let ledger = transfer_funds(&data, body.from_id, body.to_id, body.amount).await?;
+
+    let success_response = json!({
+        "status": "success",
+        "message": "转账成功",
+        "data": ledger
+    });
+
+    Ok((StatusCode::CREATED, Json(success_response)))
+}
+
+// 账户缓存的命中统计
+async fn cache_stats(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(json!({"status": "success", "data": data.cache.stats()}))
+}
+
+// 旧路由仍然可用，但在响应中标记为已废弃，并指向新的接口
+async fn deprecated(req: Request, next: Next) -> Response {
+    let successor = match req.extensions().get::<MatchedPath>().map(|p| p.as_str()) {
+        Some(path) if path.starts_with("/transfer/") => "/api/v1/transfers",
+        _ => "/api/v1/accounts",
+    };
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(
+        header::LINK,
+        format!(r#"<{}>; rel="successor-version""#, successor)
+            .parse()
+            .unwrap(),
+    );
+    response
 }
 
 #[tokio::main]
 async fn main() {
+    let config = config::AppConfig::global();
+
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&config.log.level);
 
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set.");
     let pool = match PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
+        .max_connections(config.postgres.max_connections)
+        .connect(&config.postgres.dsn)
         .await
     {
         Ok(pool) => {
@@ -280,20 +505,66 @@ async fn main() {
             std::process::exit(1);
         }
     };
-    let app_state = Arc::new(AppState { db: pool.clone() });
 
-    let routes = Router::new()
-        .route("/check", get(health_checker_handler))
+    // cargo run -- migrate [up|down|status]
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        if let Err(err) = db::migrate_command(&pool, &args[1..]).await {
+            println!("🔥 Migration failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    db::ensure_migrated(&pool).await;
+
+    let redis = match RedisPool::from_config().await {
+        Ok(redis) => redis,
+        Err(err) => {
+            println!("🔥 Failed to connect to redis: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let app_state = Arc::new(AppState {
+        db: pool.clone(),
+        cache: ReadThroughCache::from_config(redis),
+    });
+
+    let idempotent = || middleware::from_fn_with_state(pool.clone(), idempotency::idempotency);
+
+    let api_v1 = Router::new()
+        .route(
+            "/accounts",
+            get(list).merge(post(insert_lists).route_layer(idempotent())),
+        )
+        .route("/accounts/:id", get(find).patch(patch).delete(delete))
+        .route(
+            "/transfers",
+            post(create_transfer).route_layer(idempotent()),
+        )
+        .route("/cache/stats", get(cache_stats));
+
+    // 旧的接口，保留为已废弃的别名
+    let legacy = Router::new()
         .route("/", get(list))
-        .route("/insert", post(insert_lists))
+        .route("/insert", post(insert_lists).route_layer(idempotent()))
         .route("/find/:id", get(find))
         .route("/update/:id/:balance", get(update))
         .route("/delete/:id", get(delete))
-        .route("/transfer/:from_id/:to_id/:balance", get(transfer))
+        .route(
+            "/transfer/:from_id/:to_id/:balance",
+            get(transfer).route_layer(idempotent()),
+        )
+        .route_layer(middleware::from_fn(deprecated));
+
+    let routes = Router::new()
+        .route("/check", get(health_checker_handler))
+        .nest("/api/v1", api_v1)
+        .merge(legacy)
         .with_state(app_state)
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config.web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
@@ -2,6 +2,7 @@
 use std::{fmt::Display, str::FromStr};
 
 use axum::{
+    extract::State,
     routing::{get, post},
     Form, Json, Router,
 };
@@ -10,9 +11,12 @@ use serde::{Deserialize, Serialize};
 use serde_json::{from_str, json};
 use tower_http::trace::TraceLayer;
 
+mod config;
 mod logger;
 mod redis_client;
 
+use redis_client::{Codec, RedisPool, RedisStoreError};
+
 #[derive(Serialize, Deserialize)]
 pub struct UserInfo {
     pub id: i32,
@@ -20,42 +24,90 @@ pub struct UserInfo {
     pub email: String,
 }
 
-async fn set_item() -> Result<&'static str, String> {
-    redis_client::write_to_redis("author", "axum.rs").await?;
-    redis_client::write_ex_to_redis("my_key", "13131231231232", 3).await?;
+async fn set_item(State(redis): State<RedisPool>) -> Result<&'static str, RedisStoreError> {
+    redis.write("author", "axum.rs").await?;
+    redis.write_ex("my_key", "13131231231232", 3).await?;
 
     Ok("Successfully set")
 }
 
-async fn get_item() -> Result<String, String> {
-    let value: String = redis_client::read_from_redis("author").await?;
+async fn get_item(State(redis): State<RedisPool>) -> Result<String, RedisStoreError> {
+    let value: String = redis.read("author").await?;
     Ok(value)
 }
-async fn get_key() -> Result<String, String> {
-    let value: String = redis_client::read_from_redis("my_key").await?;
+async fn get_key(State(redis): State<RedisPool>) -> Result<String, RedisStoreError> {
+    let value: String = redis.read("my_key").await?;
     Ok(value)
 }
 
-async fn set_user() -> Result<&'static str, String> {
+async fn set_user(State(redis): State<RedisPool>) -> Result<&'static str, RedisStoreError> {
     let user = UserInfo {
         id: 1,
         username: "axum.rs".to_string(),
         email: "team@axum.rs".to_string(),
     };
 
-    redis_client::write_to_redis("user", json!(user).to_string()).await?;
+    redis.set_json("user", &user, None).await?;
     Ok("Successfully set user.")
 }
-async fn get_user() -> Result<Json<UserInfo>, String> {
-    let value: String = redis_client::read_from_redis("user").await?;
-    let user: UserInfo = from_str(&value).map_err(|err| err.to_string())?;
+async fn get_user(State(redis): State<RedisPool>) -> Result<Json<UserInfo>, RedisStoreError> {
+    let user: UserInfo = redis
+        .get_json("user")
+        .await?
+        .ok_or_else(|| RedisStoreError::NotFound("user".to_string()))?;
     Ok(Json(user))
 }
 
+// 批量写入，`user:` 前缀的 key 使用 MessagePack 编码
+async fn set_users(State(redis): State<RedisPool>) -> Result<&'static str, RedisStoreError> {
+    let users = [
+        (
+            "user:1",
+            UserInfo {
+                id: 1,
+                username: "axum.rs".to_string(),
+                email: "team@axum.rs".to_string(),
+            },
+        ),
+        (
+            "user:2",
+            UserInfo {
+                id: 2,
+                username: "tokio.rs".to_string(),
+                email: "team@tokio.rs".to_string(),
+            },
+        ),
+    ];
+    redis.mset(&users).await?;
+    Ok("Successfully set users.")
+}
+async fn get_users(
+    State(redis): State<RedisPool>,
+) -> Result<Json<Vec<Option<UserInfo>>>, RedisStoreError> {
+    let users = redis.mget(&["user:1", "user:2", "user:3"]).await?;
+    Ok(Json(users))
+}
+
+// 访问计数，60 秒后清零
+async fn visit(State(redis): State<RedisPool>) -> Result<Json<serde_json::Value>, RedisStoreError> {
+    let count = redis.incr_with_ttl("visits", 1, 60).await?;
+    let ttl = redis.ttl("visits").await?;
+    Ok(Json(json!({ "visits": count, "ttl": ttl })))
+}
+
 #[tokio::main]
 async fn main() {
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
+
+    // 所有请求共享同一个 Redis 连接池
+    let redis = match RedisPool::from_config().await {
+        Ok(redis) => redis.with_codec("user:", Codec::MessagePack),
+        Err(err) => {
+            println!("🔥 Failed to connect to redis: {}", err);
+            std::process::exit(1);
+        }
+    };
 
     let routes = Router::new()
         .route("/set", get(set_item))
@@ -63,9 +115,13 @@ async fn main() {
         .route("/get_key", get(get_key))
         .route("/set_user", get(set_user))
         .route("/get_user", get(get_user))
+        .route("/set_users", get(set_users))
+        .route("/get_users", get(get_users))
+        .route("/visit", get(visit))
+        .with_state(redis)
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
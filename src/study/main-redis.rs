@@ -8,6 +8,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::{from_str, json};
 use tower_http::trace::TraceLayer;
 
+mod config;
+mod logger;
+
 const REDIS_DSN: &str = "redis://127.0.0.1:6379/";
 
 #[derive(Serialize, Deserialize)]
@@ -69,10 +72,7 @@ async fn get_user() -> Result<Json<UserInfo>, String> {
 
 #[tokio::main]
 async fn main() {
-    if std::env::var_os("RUST_LOG").is_none() {
-        std::env::set_var("RUST_LOG", "tower_http=debug,middleware=debug");
-    }
-    tracing_subscriber::fmt::init();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
 
     let routes = Router::new()
         .route("/set", get(set_item))
@@ -81,7 +81,7 @@ async fn main() {
         .route("/get_user", get(get_user))
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
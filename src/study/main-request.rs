@@ -13,6 +13,8 @@ use headers::UserAgent;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
+mod config;
+
 #[tokio::main]
 async fn main() {
     let routes = Router::new()
@@ -29,7 +31,7 @@ async fn main() {
         .route("/get_user_agent", get(get_user_agent))
         .route("/get_user_agent_typed", get(get_user_agent_typed));
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
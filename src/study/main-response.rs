@@ -11,6 +11,8 @@ use serde::Serialize;
 use serde_json::{json, Value};
 use std::convert::Infallible;
 
+mod config;
+
 #[tokio::main]
 async fn main() {
     let routes = Router::new()
@@ -26,7 +28,7 @@ async fn main() {
         .route("/err", get(app_error))
         .route("/cn", get(cn));
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
@@ -13,6 +13,8 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
+mod config;
+
 /// 通过表单提交数据
 #[derive(Deserialize)]
 pub struct EditUser {
@@ -41,7 +43,7 @@ async fn main() {
         .nest("/news", news_router)
         .route("/go", get(redirect));
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
@@ -1,29 +1,55 @@
 #![allow(unused)]
 
 use axum::{
-    extract::Query,
+    extract::{FromRef, Query, State},
     http::{HeaderMap, StatusCode},
-    response::{Html, IntoResponse},
-    routing::get,
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
     Extension, Form, Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sqlx::{Pool, Postgres};
 use tower_cookies::{Cookie, CookieManagerLayer, Cookies};
 use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 
+mod authz;
+mod config;
+mod cookie_jar;
+mod db;
+mod error;
 mod logger;
 mod redis_client;
+mod secure_cookie;
+mod session;
+mod users;
 
-const SESSION_ID_COOKIE_NAME: &str = "axum_rs_session_id";
-const SESSION_KEY_PREFIX: &str = "axum_rs_session:";
+use authz::{Permission, Principal};
+use redis_client::RedisPool;
+use session::{RedisSessionStore, Session, SessionError, SessionLayer, UserSessions};
 
-/// 用户Session
-#[derive(Serialize, Deserialize, Debug)]
-pub struct UserSession {
-    pub username: String,
-    pub level: u8,
+/// Session 中保存登录用户的 key
+const USER_SESSION_KEY: &str = authz::SESSION_KEY;
+
+/// 应用状态
+#[derive(Clone)]
+struct AppState {
+    db: Pool<Postgres>,
+    user_sessions: UserSessions,
+}
+
+impl FromRef<AppState> for Pool<Postgres> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for UserSessions {
+    fn from_ref(state: &AppState) -> Self {
+        state.user_sessions.clone()
+    }
 }
 
 /// 用户登录表单
@@ -39,38 +65,6 @@ pub struct LoginMessage {
     pub msg: Option<String>,
 }
 
-// 将 Session ID 保存到 Cookie
-fn save_session_id_to_cookie(session_id: &str, headers: &mut HeaderMap) {
-    let cookie = format!("{}={}", SESSION_ID_COOKIE_NAME, session_id);
-    headers.insert(
-        axum::http::header::SET_COOKIE,
-        cookie.as_str().parse().unwrap(),
-    );
-}
-
-// 从 cookie 中获取session id
-fn get_session_from_cookie(headers: &HeaderMap) -> Option<String> {
-    let cookies: &str = headers
-        .get(axum::http::header::COOKIE)
-        .and_then(|value: &axum::http::HeaderValue| value.to_str().ok())
-        .unwrap_or("");
-    if cookies.is_empty() {
-        return None;
-    }
-    let mut session_id: Option<String> = None;
-    let cookies: Vec<&str> = cookies.split(';').collect();
-    for cookie in cookies {
-        let cookie_pair: Vec<&str> = cookie.split('=').collect();
-        let cookie_name: &str = cookie_pair[0].trim();
-        let cookie_value: &str = cookie_pair[1].trim();
-        if cookie_name == SESSION_ID_COOKIE_NAME && !cookie_value.is_empty() {
-            session_id = Some(cookie_value.to_string());
-            break;
-        }
-    }
-    session_id
-}
-
 /// 登录界面
 async fn login(Query(login_msg): Query<LoginMessage>) -> Html<String> {
     let msg = match login_msg.msg {
@@ -109,60 +103,66 @@ async fn login(Query(login_msg): Query<LoginMessage>) -> Html<String> {
 
 // 登录操作
 async fn logout_action(
+    State(db): State<Pool<Postgres>>,
+    State(user_sessions): State<UserSessions>,
+    session: Session,
     Form(frm): Form<UserLoginForm>,
-) -> Result<(StatusCode, HeaderMap, ()), String> {
+) -> Result<(StatusCode, HeaderMap, ()), Response> {
     let mut headers: HeaderMap = HeaderMap::new();
     let url: &str;
-    if !(&frm.username == "test" && &frm.password == "123123") {
-        url = "/login?msg=用户名或密码错误"
-    } else {
-        // 生成 session ID
-        let session_id = Uuid::new_v4().to_string();
-        // 将 session ID 保存到 Cookie
-        save_session_id_to_cookie(&session_id, &mut headers);
-
-        let user_session = UserSession {
-            username: frm.username,
-            level: 1,
-        };
-        let user_session = serde_json::json!(user_session).to_string();
+    let user = users::authenticate(&db, &frm.username, &frm.password)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    if let Some(user) = user {
+        // 登录后更换 Session ID，登录前的 ID 即使被他人获取也无法使用
+        let session_id = session
+            .regenerate()
+            .await
+            .map_err(IntoResponse::into_response)?;
+        user_sessions
+            .add(&user.username, &session_id)
+            .await
+            .map_err(IntoResponse::into_response)?;
 
-        // 将 session 保存到 redis
-        let redis_key = format!("{}{}", SESSION_KEY_PREFIX, session_id);
-        redis_client::write_ex_to_redis(redis_key, user_session, 1200).await?;
+        // 浏览器登录的用户不单独申请权限范围，拥有角色允许的全部范围
+        let user_session = Principal {
+            subject: user.username,
+            roles: user.roles,
+            scopes: None,
+        };
+        // 保存到 Session，SessionLayer 会在响应时写入存储并下发 Cookie
+        session
+            .insert(USER_SESSION_KEY, user_session)
+            .await
+            .map_err(IntoResponse::into_response)?;
         url = "/"
+    } else {
+        url = "/login?msg=用户名或密码错误"
     }
     headers.insert(axum::http::header::LOCATION, url.parse().unwrap());
     Ok((StatusCode::FOUND, headers, ()))
 }
 
 // 退出登录
-async fn logout(headers: HeaderMap) -> Result<(StatusCode, HeaderMap, ()), String> {
-    let session_id = get_session_from_cookie(&headers);
-    let mut headers = HeaderMap::new();
-    if let Some(session_id) = session_id {
-        // 从 redis 删除 Session
-        let redis_key = format!("{}{}", SESSION_KEY_PREFIX, session_id);
-        redis_client::delete_from_redis(&redis_key).await?;
-        save_session_id_to_cookie(&session_id, &mut headers);
+async fn logout(
+    State(user_sessions): State<UserSessions>,
+    session: Session,
+) -> Result<(StatusCode, HeaderMap, ()), SessionError> {
+    let user: Option<Principal> = session.get(USER_SESSION_KEY).await?;
+    if let (Some(user), Some(session_id)) = (user, session.id().await) {
+        user_sessions.remove(&user.subject, &session_id).await?;
     }
+    // 已经过期的 Session 同样视为退出成功；响应中会让浏览器删除 Cookie
+    session.destroy().await?;
+    let mut headers = HeaderMap::new();
     headers.insert(axum::http::header::LOCATION, "/login".parse().unwrap());
     Ok((StatusCode::FOUND, headers, ()))
 }
 
 // 首页
-async fn index(headers: HeaderMap) -> Result<Html<String>, String> {
-    let session_id = get_session_from_cookie(&headers);
-    let mut session: Option<UserSession> = None;
-    if let Some(session_id) = session_id {
-        let redis_key: String = format!("{}{}", SESSION_KEY_PREFIX, session_id);
-        let session_str: Option<String> = Some(redis_client::read_from_redis(&redis_key).await?);
-        if let Some(session_str) = session_str {
-            let user_session: UserSession =
-                serde_json::from_str(&session_str).map_err(|err| err.to_string())?;
-            session = Some(user_session);
-        }
-    }
+async fn index(session: Session) -> Result<Html<String>, SessionError> {
+    // Session 不存在或已过期时按未登录处理
+    let session: Option<Principal> = session.get(USER_SESSION_KEY).await?;
 
     match session {
         Some(session) => {
@@ -178,31 +178,113 @@ async fn index(headers: HeaderMap) -> Result<Html<String>, String> {
                         </title>
                     </head>
                     <body>
-                        <div>欢迎 {} ! 你的等级是 {}。</div>
+                        <div>欢迎 {} ! 你的角色是 {}。</div>
                         <div><a href="/logout">退出登录</a></div>
+                        <form action="/logout/all" method="post">
+                            <button type="submit">退出所有设备</button>
+                        </form>
                     </body>
                     </html>"#,
-                session.username, session.level
+                session.subject,
+                session.roles.join("、")
             );
             Ok(Html(html))
         }
-        None => Err("Please login via /login page".to_string()),
+        None => Ok(Html("Please login via /login page".to_string())),
+    }
+}
+
+// 当前用户的所有登录 Session
+async fn sessions(
+    State(user_sessions): State<UserSessions>,
+    session: Session,
+) -> Result<impl IntoResponse, SessionError> {
+    let Some(user) = session.get::<Principal>(USER_SESSION_KEY).await? else {
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"status": "fail", "message": "Please login via /login page"})),
+        ));
+    };
+    let current = session.id().await;
+    let data: Vec<_> = user_sessions
+        .list(&user.subject)
+        .await?
+        .into_iter()
+        .map(|id| json!({"current": Some(&id) == current.as_ref(), "id": id}))
+        .collect();
+    Ok((
+        StatusCode::OK,
+        Json(json!({"status": "success", "data": data})),
+    ))
+}
+
+// 退出所有设备；只接受 POST，避免被第三方页面的链接或图片触发
+async fn logout_all(
+    State(user_sessions): State<UserSessions>,
+    session: Session,
+) -> Result<(StatusCode, HeaderMap, ()), SessionError> {
+    if let Some(user) = session.get::<Principal>(USER_SESSION_KEY).await? {
+        user_sessions.revoke_all(&user.subject).await?;
     }
+    session.destroy().await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(axum::http::header::LOCATION, "/login".parse().unwrap());
+    Ok((StatusCode::FOUND, headers, ()))
+}
+
+// 管理后台首页，由路由守卫检查管理员角色
+async fn admin(principal: Principal) -> Html<String> {
+    Html(format!("管理员 {}，你好！", principal.subject))
 }
 
 #[tokio::main]
 async fn main() {
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
+
+    let db = db::connect().await;
+
+    // 所有请求共享同一个 Redis 连接池
+    let redis = match RedisPool::from_config().await {
+        Ok(redis) => redis,
+        Err(err) => {
+            println!("🔥 Failed to connect to redis: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // 也可以换成 MemorySessionStore 或 PostgresSessionStore
+    let store = RedisSessionStore::new(redis.clone());
+    let user_sessions = UserSessions::new(redis, store.clone());
+    let state = AppState {
+        db: db.clone(),
+        user_sessions,
+    };
 
     let routes = Router::new()
         .route("/", get(index))
         .route("/login", get(login).post(logout_action))
         .route("/logout", get(logout))
+        .route("/logout/all", post(logout_all))
+        .route("/sessions", get(sessions))
+        // 整组路由都需要管理员角色
+        .nest(
+            "/admin",
+            Router::new()
+                .route("/", get(admin))
+                .route_layer(middleware::from_fn_with_state(
+                    Permission::role("admin"),
+                    authz::guard,
+                )),
+        )
+        // 注册、登录和修改密码的 JSON 接口
+        .nest("/users", users::router().with_state(db))
+        .with_state(state)
+        .layer(SessionLayer::new(store))
         .layer(CookieManagerLayer::new())
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
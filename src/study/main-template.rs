@@ -3,6 +3,7 @@
 use askama::Template;
 use axum::{response::Html, routing::get, Router};
 use tower_http::trace::TraceLayer;
+mod config;
 mod logger;
 
 #[derive(Template)]
@@ -24,13 +25,13 @@ async fn index() -> Result<Html<String>, String> {
 #[tokio::main]
 async fn main() {
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&config::AppConfig::global().log.level);
 
     let routes = Router::new()
         .route("/", get(index))
         .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config::AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
@@ -1,26 +1,46 @@
 #![allow(unused)]
 
-use std::{
-    fs::{self, File},
-    io::Write,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::{sync::Arc, time::Duration};
 
 use axum::{
-    extract::Multipart,
-    http::HeaderMap,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::StatusCode,
+    middleware,
     response::{Html, IntoResponse},
     routing::{get, post},
-    Router,
+    Json, Router,
 };
+use serde_json::json;
 use tower_http::trace::TraceLayer;
-use uuid::Uuid;
+
+mod api_keys;
+mod auth;
+mod authz;
+mod blob_store;
+mod config;
+mod cookie_jar;
+mod db;
+mod error;
+mod files;
+mod jwt;
 mod logger;
+mod redis_client;
+mod secure_cookie;
+mod session;
+mod tus;
+mod upload;
+mod users;
 
-/// 允许上传的大小
-const MAX_REQUEST_SIZE: usize = 20 * 1024 * 1024; // 设置请求体大小限制为 20MB
-const MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 10; // 10MB
-const SAVE_FILE_BASE_PATH: &str = "uploads";
+use auth::Authenticator;
+use authz::RequireScope;
+use blob_store::{BlobError, BlobStore};
+use config::AppConfig;
+use files::{FilesRead, FilesState, FilesWrite, Owner};
+use jwt::{Keys, TokenStore};
+use redis_client::RedisPool;
+use session::{RedisSessionStore, SessionLayer};
+use tus::TusState;
+use upload::UploadError;
 
 // 上传文件的页面
 async fn upload_page() -> impl IntoResponse {
@@ -28,7 +48,7 @@ async fn upload_page() -> impl IntoResponse {
         r#"
         <body>
             <form action="do_upload" method="post" enctype="multipart/form-data">
-                <input type="file" name="uploadFile">
+                <input type="file" name="uploadFile" multiple>
                 <input type="submit" value="Upload">
             </form>
         <body>
@@ -36,62 +56,113 @@ async fn upload_page() -> impl IntoResponse {
     )
 }
 
-// 将上传文件写到本地
-async fn do_upload(mut multipart: Multipart) -> impl IntoResponse {
-    while let Some(mut file) = multipart.next_field().await.expect("next file failed") {
-        //文件类型
-        let content_type = file.content_type().unwrap().to_string();
-        println!("content_type-->{}", content_type);
-
-        // 文件名字
-        let filename = file.file_name().unwrap().to_string();
-        // 获取当前时间戳
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        // 文件上传路径
-        let filepath = format!("{}/{}_{}", SAVE_FILE_BASE_PATH, timestamp, filename);
-        // 创建
-        let mut upload_file = match File::create(&filepath) {
-            Ok(file) => file,
-            Err(_) => return Html("Failed to create file"),
-        };
-
-        let mut total_size: u64 = 0;
-
-        while let Some(chunk) = file.chunk().await.unwrap() {
-            total_size += chunk.len() as u64;
-            if total_size > 10 * 1024 * 1024 {
-                // 如果文件大小超过 10MB，返回请求实体过大的错误响应
-                return Html("File size exceeds the limit (10MB)!");
-            }
-            if let Err(_) = upload_file.write_all(&chunk) {
-                return Html("Failed to write file");
-            }
-        }
-    }
+// 保存上传文件并登记到当前用户的文件目录，返回每个文件的记录
+async fn do_upload(
+    State(state): State<FilesState>,
+    _: RequireScope<FilesWrite>,
+    Owner(user_id): Owner,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, UploadError> {
+    let staged = upload::receive_all(&AppConfig::global().upload, multipart).await?;
+    let files = files::save_all(&state.db, state.store.as_ref(), user_id, staged).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"status": "success", "data": files})),
+    ))
+}
 
-    Html("upload successful")
+// 下载引入文件目录之前上传的文件，这些文件没有所有者，登录后可以下载
+async fn download_legacy(
+    State(state): State<FilesState>,
+    _: RequireScope<FilesRead>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, BlobError> {
+    upload::download_legacy(state.store.as_ref(), &id).await
 }
 
 #[tokio::main]
 async fn main() {
     // 初始化日志记录器
-    logger::init_logger();
+    logger::init_logger_with(&AppConfig::global().log.level);
+
+    let config = &AppConfig::global().upload;
+
+    // 创建保存目录，使用 S3 时也用来保存未完成的上传
+    if let Err(err) = upload::prepare_dir(config).await {
+        println!(
+            "🔥 Failed to create upload directory {}: {}",
+            config.dir, err
+        );
+        std::process::exit(1);
+    }
+    // 本地目录或 S3 兼容的对象存储，由 upload.backend 决定
+    let store = match blob_store::from_config(config) {
+        Ok(store) => store,
+        Err(err) => {
+            println!("🔥 Failed to create blob store: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let db = db::connect().await;
+    let redis = match RedisPool::from_config().await {
+        Ok(redis) => redis,
+        Err(err) => {
+            println!("🔥 Failed to connect to redis: {}", err);
+            std::process::exit(1);
+        }
+    };
+    // 启动时加载签名密钥，配置错误时尽早退出
+    Keys::global();
+
+    // API Key、Bearer 访问令牌和 Session 都可以用来认证
+    let authenticator = Authenticator::new(db.clone(), TokenStore::from_config(redis.clone()));
+    let require_auth = middleware::from_fn_with_state(authenticator, auth::require_auth);
+
+    // 定期清理过期的断点续传
+    let tus = TusState::new(db.clone(), store.clone());
+    tokio::spawn({
+        let tus = tus.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(10 * 60));
+            loop {
+                interval.tick().await;
+                match tus.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("purged {} expired uploads", n),
+                    Err(err) => tracing::error!("failed to purge expired uploads: {}", err),
+                }
+            }
+        }
+    });
 
     let routes = Router::new()
         .route("/upload_page", get(upload_page))
-        .route("/do_upload", post(do_upload))
+        .route(
+            "/do_upload",
+            post(do_upload).route_layer(require_auth.clone()),
+        )
+        .route(
+            "/files/:id",
+            get(download_legacy).route_layer(require_auth.clone()),
+        )
+        // 当前用户的文件目录：列表、详情、下载和删除
+        .nest(
+            "/api/files",
+            files::router().route_layer(require_auth.clone()),
+        )
+        // 请求体大小限制，替换 axum 默认的 2MB
+        .layer(DefaultBodyLimit::max(config.max_request_size))
+        .with_state(FilesState { db, store })
+        // tus 断点续传，适合大文件和不稳定的网络
+        .nest(
+            "/uploads",
+            tus::router().route_layer(require_auth).with_state(tus),
+        )
+        .layer(SessionLayer::new(RedisSessionStore::new(redis)))
         .layer(TraceLayer::new_for_http());
 
-    // 创建 "uploads" 文件夹
-    if let Err(err) = tokio::fs::create_dir_all("uploads").await {
-        eprintln!("Failed to create 'uploads' directory: {}", err);
-        return;
-    }
-
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&AppConfig::global().web.addr)
         .await
         .unwrap();
     println!("listening on http://{}", listener.local_addr().unwrap());
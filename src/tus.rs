@@ -0,0 +1,989 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    extract::{FromRef, OriginalUri, Path as UrlPath, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{head, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+use crate::authz::RequireScope;
+use crate::blob_store::BlobStore;
+use crate::config::{AppConfig, UploadConfig};
+use crate::files::{self, FileRecord, FilesWrite, Owner};
+use crate::upload::{self, StoredFile, UploadError};
+
+/// 支持的 tus 协议版本
+pub const TUS_VERSION: &str = "1.0.0";
+/// 支持的扩展
+const TUS_EXTENSIONS: &str = "creation,expiration,checksum";
+/// 支持的校验算法
+const CHECKSUM_ALGORITHMS: &str = "sha1,sha256";
+/// PATCH 请求体的类型
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+/// 保存目录下存放未完成的断点续传的子目录，重启后保留
+const TUS_DIR: &str = ".tus";
+/// 读取文件时的缓冲区大小
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const TUS_CHECKSUM_ALGORITHM: HeaderName = HeaderName::from_static("tus-checksum-algorithm");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+const UPLOAD_CHECKSUM: HeaderName = HeaderName::from_static("upload-checksum");
+const UPLOAD_DEFER_LENGTH: HeaderName = HeaderName::from_static("upload-defer-length");
+/// 上传完成后文件在目录中的 id，不属于 tus 协议
+const FILE_ID: HeaderName = HeaderName::from_static("x-file-id");
+
+/// 断点续传的状态
+#[derive(Clone)]
+pub struct TusState {
+    db: Pool<Postgres>,
+    store: Arc<dyn BlobStore>,
+    /// 正在写入的上传，同一个上传同时只能有一个 PATCH
+    locks: Arc<Mutex<HashSet<String>>>,
+}
+
+impl TusState {
+    pub fn new(db: Pool<Postgres>, store: Arc<dyn BlobStore>) -> Self {
+        Self {
+            db,
+            store,
+            locks: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn lock(&self, id: &str) -> Option<UploadLock> {
+        let mut locks = self.locks.lock().unwrap();
+        if !locks.insert(id.to_string()) {
+            return None;
+        }
+        Some(UploadLock {
+            locks: self.locks.clone(),
+            id: id.to_string(),
+        })
+    }
+
+    /// 删除过期的上传，返回删除的数量；正在写入的上传会被跳过
+    pub async fn purge_expired(&self) -> std::io::Result<usize> {
+        self.purge(&AppConfig::global().upload, Utc::now()).await
+    }
+
+    // 删除在 `now` 之前过期的上传；信息文件无法解析时，按它的修改时间计算过期时间
+    async fn purge(&self, config: &UploadConfig, now: DateTime<Utc>) -> std::io::Result<usize> {
+        let mut entries = match fs::read_dir(tus_dir(config)).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        let mut count = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".json")) else {
+                continue;
+            };
+            let Some(_lock) = self.lock(id) else {
+                continue;
+            };
+            let expires_at = match read_info(config, id).await {
+                Ok(Some(info)) => info.expires_at,
+                Ok(None) => continue,
+                Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                    let modified: DateTime<Utc> = entry.metadata().await?.modified()?.into();
+                    tracing::warn!("corrupted tus upload {}: {}", id, err);
+                    modified + expiration(config)
+                }
+                Err(err) => {
+                    tracing::warn!("failed to read tus upload {}: {}", id, err);
+                    continue;
+                }
+            };
+            if expires_at <= now {
+                remove(config, id).await;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl FromRef<TusState> for Pool<Postgres> {
+    fn from_ref(state: &TusState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// 上传期间持有的锁，离开作用域时释放
+struct UploadLock {
+    locks: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        self.locks.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// 上传的信息，保存为 `{id}.json`，未完成时数据保存在同目录的 `{id}` 中
+///
+/// 完成后只保留信息，直到过期，客户端可以继续查询进度。
+#[derive(Serialize, Deserialize)]
+struct UploadInfo {
+    /// 创建上传的用户
+    user_id: i32,
+    /// 文件的总字节数
+    length: u64,
+    /// `Upload-Metadata` 中的 `filename`，已清理
+    filename: Option<String>,
+    created_at: DateTime<Utc>,
+    /// 每次写入后顺延
+    expires_at: DateTime<Utc>,
+    /// 完成后文件在目录中的 id
+    file_id: Option<i32>,
+}
+
+/// 断点续传错误
+#[derive(Debug)]
+pub enum TusError {
+    /// 缺少 `Tus-Resumable` 或版本不支持
+    UnsupportedVersion,
+    /// 上传不存在
+    NotFound,
+    /// 上传已过期
+    Gone,
+    /// 请求头缺失或格式错误
+    BadRequest(String),
+    /// `Upload-Offset` 与当前偏移量不一致
+    OffsetMismatch { expected: u64 },
+    /// PATCH 的 `Content-Type` 不是 `application/offset+octet-stream`
+    UnsupportedMediaType,
+    /// `Upload-Length` 超过单个文件的大小限制
+    TooLarge { limit: u64 },
+    /// 写入的数据超过 `Upload-Length`
+    ExceedsLength { length: u64 },
+    /// 同一个上传正在被另一个请求写入
+    Locked,
+    /// 数据与 `Upload-Checksum` 不一致
+    ChecksumMismatch,
+    /// 文件类型不允许上传，或保存失败
+    Upload(UploadError),
+    /// 读写文件失败
+    Io(std::io::Error),
+}
+
+impl Display for TusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TusError::UnsupportedVersion => {
+                write!(f, "Tus-Resumable must be {}", TUS_VERSION)
+            }
+            TusError::NotFound => write!(f, "upload not found"),
+            TusError::Gone => write!(f, "upload has expired"),
+            TusError::BadRequest(message) => write!(f, "{}", message),
+            TusError::OffsetMismatch { expected } => {
+                write!(f, "Upload-Offset does not match, expected {}", expected)
+            }
+            TusError::UnsupportedMediaType => {
+                write!(f, "Content-Type must be {}", OFFSET_OCTET_STREAM)
+            }
+            TusError::TooLarge { limit } => {
+                write!(f, "Upload-Length exceeds the limit of {} bytes", limit)
+            }
+            TusError::ExceedsLength { length } => {
+                write!(f, "data exceeds Upload-Length of {} bytes", length)
+            }
+            TusError::Locked => write!(f, "upload is being written by another request"),
+            TusError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            TusError::Upload(err) => write!(f, "{}", err),
+            TusError::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TusError {}
+
+impl From<std::io::Error> for TusError {
+    fn from(err: std::io::Error) -> Self {
+        TusError::Io(err)
+    }
+}
+
+impl From<UploadError> for TusError {
+    fn from(err: UploadError) -> Self {
+        TusError::Upload(err)
+    }
+}
+
+impl IntoResponse for TusError {
+    fn into_response(self) -> Response {
+        // 文件类型不允许、保存失败时与普通上传的响应相同
+        if let TusError::Upload(err) = self {
+            return err.into_response();
+        }
+        let (status, code) = match &self {
+            TusError::Upload(_) => unreachable!(),
+            TusError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+            TusError::UnsupportedVersion => {
+                (StatusCode::PRECONDITION_FAILED, "unsupported_version")
+            }
+            TusError::NotFound => (StatusCode::NOT_FOUND, "not_found"),
+            TusError::Gone => (StatusCode::GONE, "expired"),
+            TusError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            TusError::OffsetMismatch { .. } => (StatusCode::CONFLICT, "offset_mismatch"),
+            TusError::UnsupportedMediaType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type")
+            }
+            TusError::TooLarge { .. } | TusError::ExceedsLength { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "too_large")
+            }
+            TusError::Locked => (StatusCode::LOCKED, "locked"),
+            // tus 校验扩展规定的状态码
+            TusError::ChecksumMismatch => (StatusCode::from_u16(460).unwrap(), "checksum_mismatch"),
+        };
+        let message = if status.is_server_error() {
+            tracing::error!("{}", self);
+            "Internal Server Error".to_string()
+        } else {
+            self.to_string()
+        };
+        let body = Json(json!({
+            "status": if status.is_server_error() { "error" } else { "fail" },
+            "code": code,
+            "message": message,
+        }));
+        let mut response = (status, body).into_response();
+        if matches!(self, TusError::UnsupportedVersion) {
+            response
+                .headers_mut()
+                .insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+        }
+        response
+    }
+}
+
+/// tus 1.0 断点续传路由：`OPTIONS /`、`POST /`、`HEAD /:id`、`PATCH /:id`
+///
+/// 需要已认证的身份，上传只对创建者可见。上传完成后和普通上传一样保存并登记到文件目录，
+/// 最后一个 PATCH 和之后的 HEAD 通过 `X-File-Id` 返回文件在目录中的 id。
+pub fn router() -> Router<TusState> {
+    Router::new()
+        .route("/", post(create).options(options))
+        .route("/:id", head(progress).patch(append))
+        .layer(middleware::from_fn(tus_resumable))
+}
+
+// 检查请求的协议版本，并在所有响应中加上 `Tus-Resumable`
+async fn tus_resumable(request: Request, next: Next) -> Response {
+    let supported = request.method() == Method::OPTIONS
+        || request
+            .headers()
+            .get(TUS_RESUMABLE)
+            .is_some_and(|value| value == TUS_VERSION);
+    let mut response = if supported {
+        next.run(request).await
+    } else {
+        TusError::UnsupportedVersion.into_response()
+    };
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+// 服务端支持的版本、扩展和限制
+async fn options() -> impl IntoResponse {
+    let config = &AppConfig::global().upload;
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_VERSION_HEADER, TUS_VERSION.to_string()),
+            (TUS_EXTENSION, TUS_EXTENSIONS.to_string()),
+            (TUS_MAX_SIZE, config.max_upload_size.to_string()),
+            (TUS_CHECKSUM_ALGORITHM, CHECKSUM_ALGORITHMS.to_string()),
+        ],
+    )
+}
+
+// 创建上传，返回上传地址
+async fn create(
+    _: RequireScope<FilesWrite>,
+    Owner(user_id): Owner,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, TusError> {
+    let config = &AppConfig::global().upload;
+    if headers.contains_key(UPLOAD_DEFER_LENGTH) {
+        return Err(TusError::BadRequest(
+            "Upload-Defer-Length is not supported".to_string(),
+        ));
+    }
+    let length = upload_length(config, &headers)?;
+    let metadata = parse_metadata(headers.get(UPLOAD_METADATA))?;
+
+    let id = Uuid::new_v4().simple().to_string();
+    let now = Utc::now();
+    let info = UploadInfo {
+        user_id,
+        length,
+        filename: metadata
+            .get("filename")
+            .map(|name| upload::sanitize_filename(name)),
+        created_at: now,
+        expires_at: now + expiration(config),
+        file_id: None,
+    };
+    fs::create_dir_all(tus_dir(config)).await?;
+    // 先写入信息再创建数据文件，中途失败时留下的信息文件会在过期后被清理
+    write_info(config, &id, &info).await?;
+    fs::File::create(data_path(config, &id)).await?;
+
+    let location = format!("{}/{}", uri.path().trim_end_matches('/'), id);
+    Ok((
+        StatusCode::CREATED,
+        [
+            (header::LOCATION, location),
+            (UPLOAD_EXPIRES, http_date(info.expires_at)),
+        ],
+    ))
+}
+
+// 查询上传进度
+async fn progress(
+    _: RequireScope<FilesWrite>,
+    Owner(user_id): Owner,
+    UrlPath(id): UrlPath<String>,
+) -> Result<impl IntoResponse, TusError> {
+    let config = &AppConfig::global().upload;
+    let info = find_info(config, user_id, &id).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert(UPLOAD_LENGTH, info.length.into());
+    match info.file_id {
+        Some(file_id) => {
+            headers.insert(UPLOAD_OFFSET, info.length.into());
+            headers.insert(FILE_ID, file_id.into());
+        }
+        None => {
+            headers.insert(UPLOAD_OFFSET, current_offset(config, &id).await?.into());
+            headers.insert(
+                UPLOAD_EXPIRES,
+                HeaderValue::from_str(&http_date(info.expires_at)).unwrap(),
+            );
+        }
+    }
+    Ok((StatusCode::OK, headers))
+}
+
+// 从 `Upload-Offset` 处追加数据；写满 `Upload-Length` 后保存到存储后端
+async fn append(
+    State(state): State<TusState>,
+    _: RequireScope<FilesWrite>,
+    Owner(user_id): Owner,
+    UrlPath(id): UrlPath<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse, TusError> {
+    let config = &AppConfig::global().upload;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if content_type != Some(OFFSET_OCTET_STREAM) {
+        return Err(TusError::UnsupportedMediaType);
+    }
+    let offset = header_u64(&headers, &UPLOAD_OFFSET)?
+        .ok_or_else(|| TusError::BadRequest("Upload-Offset is required".to_string()))?;
+    let checksum = parse_checksum(headers.get(UPLOAD_CHECKSUM))?;
+
+    let _lock = state.lock(&id).ok_or(TusError::Locked)?;
+    let mut info = find_info(config, user_id, &id).await?;
+    // 已经完成，客户端可能没有收到上次的响应
+    if let Some(file_id) = info.file_id {
+        if offset != info.length {
+            return Err(TusError::OffsetMismatch {
+                expected: info.length,
+            });
+        }
+        let mut response = HeaderMap::new();
+        response.insert(UPLOAD_OFFSET, info.length.into());
+        response.insert(FILE_ID, file_id.into());
+        return Ok((StatusCode::NO_CONTENT, response));
+    }
+    let current = current_offset(config, &id).await?;
+    if offset != current {
+        return Err(TusError::OffsetMismatch { expected: current });
+    }
+    // 开始写入时就顺延，传输中断后客户端仍然可以继续
+    info.expires_at = Utc::now() + expiration(config);
+    write_info(config, &id, &info).await?;
+
+    let path = data_path(config, &id);
+    let mut file = fs::OpenOptions::new().append(true).open(&path).await?;
+    // 带校验和时，校验通过前写入的数据在失败或请求被取消时都要丢弃；
+    // 不带校验和时保留已收到的数据，客户端可以从断开的位置继续
+    let mut rollback = checksum
+        .as_ref()
+        .map(|_| Rollback::new(path.clone(), current));
+    let mut hasher = checksum.as_ref().map(|(algorithm, _)| algorithm.hasher());
+    let mut written: u64 = 0;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|err| TusError::BadRequest(err.to_string()))?;
+        if current + written + chunk.len() as u64 > info.length {
+            file.set_len(current).await?;
+            return Err(TusError::ExceedsLength {
+                length: info.length,
+            });
+        }
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&chunk);
+        }
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+    }
+    file.sync_data().await?;
+    if let (Some((_, expected)), Some(hasher)) = (checksum, hasher) {
+        if hasher.finalize() != expected {
+            return Err(TusError::ChecksumMismatch);
+        }
+    }
+    if let Some(rollback) = rollback.as_mut() {
+        rollback.armed = false;
+    }
+
+    let offset = current + written;
+    // 尽早检查文件类型，不允许的文件不必等到传完
+    let sniff_length = (upload::SNIFF_LENGTH as u64).min(info.length);
+    if current < sniff_length && offset >= sniff_length {
+        let head = read_head(&path).await?;
+        if let Err(err) = upload::sniff(config, &filename(&info), &head) {
+            remove(config, &id).await;
+            return Err(TusError::Upload(err));
+        }
+    }
+
+    let mut response = HeaderMap::new();
+    response.insert(UPLOAD_OFFSET, offset.into());
+    if offset == info.length {
+        let file = complete(&state, config, &id, &mut info).await?;
+        response.insert(FILE_ID, file.id.into());
+    } else {
+        info.expires_at = Utc::now() + expiration(config);
+        write_info(config, &id, &info).await?;
+        response.insert(
+            UPLOAD_EXPIRES,
+            HeaderValue::from_str(&http_date(info.expires_at)).unwrap(),
+        );
+    }
+    Ok((StatusCode::NO_CONTENT, response))
+}
+
+// 上传完成：识别类型、计算哈希后保存并登记到文件目录，与普通上传相同
+async fn complete(
+    state: &TusState,
+    config: &UploadConfig,
+    id: &str,
+    info: &mut UploadInfo,
+) -> Result<FileRecord, TusError> {
+    let path = data_path(config, id);
+    let filename = filename(info);
+    let content_type = match upload::sniff(config, &filename, &read_head(&path).await?) {
+        Ok(content_type) => content_type,
+        Err(err) => {
+            remove(config, id).await;
+            return Err(TusError::Upload(err));
+        }
+    };
+
+    let mut file = fs::File::open(&path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+
+    let stored = StoredFile {
+        key: id.to_string(),
+        filename,
+        content_type,
+        size: info.length,
+        sha256: hex::encode(hasher.finalize()),
+        uploaded_at: Utc::now(),
+    };
+    let record = files::save(
+        &state.db,
+        state.store.as_ref(),
+        info.user_id,
+        &path,
+        &stored,
+    )
+    .await?;
+
+    // 保留信息直到过期，数据已经保存到存储后端或者与已有文件重复
+    info.file_id = Some(record.id);
+    info.expires_at = Utc::now() + expiration(config);
+    write_info(config, id, info).await?;
+    if let Err(err) = fs::remove_file(&path).await {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("failed to remove {}: {}", path.display(), err);
+        }
+    }
+    Ok(record)
+}
+
+/// 校验算法
+#[derive(Debug)]
+enum ChecksumAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    fn hasher(&self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::Sha1 => ChecksumHasher::Sha1(Sha1::new()),
+            ChecksumAlgorithm::Sha256 => ChecksumHasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+enum ChecksumHasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl ChecksumHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Sha1(hasher) => hasher.update(data),
+            ChecksumHasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            ChecksumHasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            ChecksumHasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// 离开作用域时把文件截断回写入前的长度，除非已经解除
+struct Rollback {
+    path: PathBuf,
+    len: u64,
+    armed: bool,
+}
+
+impl Rollback {
+    fn new(path: PathBuf, len: u64) -> Self {
+        Self {
+            path,
+            len,
+            armed: true,
+        }
+    }
+}
+
+impl Drop for Rollback {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        // 请求被取消时没有机会执行异步代码，只能同步截断
+        let result = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_len(self.len));
+        if let Err(err) = result {
+            tracing::warn!("failed to truncate {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+fn tus_dir(config: &UploadConfig) -> PathBuf {
+    Path::new(&config.dir).join(TUS_DIR)
+}
+
+fn data_path(config: &UploadConfig, id: &str) -> PathBuf {
+    tus_dir(config).join(id)
+}
+
+fn info_path(config: &UploadConfig, id: &str) -> PathBuf {
+    tus_dir(config).join(format!("{}.json", id))
+}
+
+fn expiration(config: &UploadConfig) -> Duration {
+    Duration::seconds(config.tus_expiration_secs as i64)
+}
+
+fn filename(info: &UploadInfo) -> String {
+    upload::sanitize_filename(info.filename.as_deref().unwrap_or_default())
+}
+
+async fn read_info(config: &UploadConfig, id: &str) -> std::io::Result<Option<UploadInfo>> {
+    match fs::read(info_path(config, id)).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+// 用户的上传，不存在、不属于该用户时返回 404，过期时返回 410
+async fn find_info(config: &UploadConfig, user_id: i32, id: &str) -> Result<UploadInfo, TusError> {
+    if !upload::is_key(id) {
+        return Err(TusError::NotFound);
+    }
+    let info = read_info(config, id).await?.ok_or(TusError::NotFound)?;
+    if info.user_id != user_id {
+        return Err(TusError::NotFound);
+    }
+    if info.expires_at <= Utc::now() {
+        return Err(TusError::Gone);
+    }
+    Ok(info)
+}
+
+// 先写临时文件再重命名，避免留下写了一半的信息
+async fn write_info(config: &UploadConfig, id: &str, info: &UploadInfo) -> std::io::Result<()> {
+    let path = info_path(config, id);
+    let temp = tus_dir(config).join(format!("{}.json.part", id));
+    fs::write(
+        &temp,
+        serde_json::to_vec(info).map_err(std::io::Error::other)?,
+    )
+    .await?;
+    fs::rename(&temp, &path).await
+}
+
+// 已收到的字节数就是数据文件的大小
+async fn current_offset(config: &UploadConfig, id: &str) -> Result<u64, TusError> {
+    match fs::metadata(data_path(config, id)).await {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(TusError::NotFound),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// 读取文件开头用于识别类型的字节
+async fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    let file = fs::File::open(path).await?;
+    let mut head = Vec::with_capacity(upload::SNIFF_LENGTH);
+    file.take(upload::SNIFF_LENGTH as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(head)
+}
+
+// 删除上传的信息和数据，失败只记录日志
+async fn remove(config: &UploadConfig, id: &str) {
+    for path in [info_path(config, id), data_path(config, id)] {
+        if let Err(err) = fs::remove_file(&path).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("failed to remove {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+// 上传的总长度；空文件无法识别类型，不允许上传
+fn upload_length(config: &UploadConfig, headers: &HeaderMap) -> Result<u64, TusError> {
+    let length = header_u64(headers, &UPLOAD_LENGTH)?
+        .ok_or_else(|| TusError::BadRequest("Upload-Length is required".to_string()))?;
+    if length == 0 {
+        return Err(TusError::BadRequest(
+            "Upload-Length must be greater than 0".to_string(),
+        ));
+    }
+    if length > config.max_upload_size {
+        return Err(TusError::TooLarge {
+            limit: config.max_upload_size,
+        });
+    }
+    Ok(length)
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Result<Option<u64>, TusError> {
+    let Some(value) = headers.get(name) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .map(Some)
+        .ok_or_else(|| TusError::BadRequest(format!("{} must be a non-negative integer", name)))
+}
+
+// `Upload-Metadata`：逗号分隔的 `key base64(value)`，值可以省略
+fn parse_metadata(value: Option<&HeaderValue>) -> Result<HashMap<String, String>, TusError> {
+    let mut metadata = HashMap::new();
+    let Some(value) = value else {
+        return Ok(metadata);
+    };
+    let invalid = || TusError::BadRequest("invalid Upload-Metadata".to_string());
+    for pair in value.to_str().map_err(|_| invalid())?.split(',') {
+        let pair = pair.trim();
+        if pair.is_empty() {
+            continue;
+        }
+        let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+        let decoded = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(invalid)?;
+        metadata.insert(key.to_string(), decoded);
+    }
+    Ok(metadata)
+}
+
+// `Upload-Checksum`：`算法 base64(摘要)`
+fn parse_checksum(
+    value: Option<&HeaderValue>,
+) -> Result<Option<(ChecksumAlgorithm, Vec<u8>)>, TusError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let invalid = || TusError::BadRequest("invalid Upload-Checksum".to_string());
+    let (algorithm, encoded) = value
+        .to_str()
+        .map_err(|_| invalid())?
+        .split_once(' ')
+        .ok_or_else(invalid)?;
+    let algorithm = match algorithm {
+        "sha1" => ChecksumAlgorithm::Sha1,
+        "sha256" => ChecksumAlgorithm::Sha256,
+        other => {
+            return Err(TusError::BadRequest(format!(
+                "unsupported checksum algorithm `{}`, supported: {}",
+                other, CHECKSUM_ALGORITHMS
+            )))
+        }
+    };
+    let expected = STANDARD.decode(encoded.trim()).map_err(|_| invalid())?;
+    Ok(Some((algorithm, expected)))
+}
+
+// RFC 7231 格式的时间，如 `Sun, 18 Oct 2026 10:00:00 GMT`
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob_store::LocalBlobStore;
+
+    fn header(value: &str) -> HeaderValue {
+        HeaderValue::from_str(value).unwrap()
+    }
+
+    fn upload_config(dir: &Path) -> UploadConfig {
+        UploadConfig {
+            dir: dir.to_str().unwrap().to_string(),
+            max_upload_size: 1000,
+            tus_expiration_secs: 60,
+            ..UploadConfig::default()
+        }
+    }
+
+    // 清理不会访问数据库
+    fn tus_state(config: &UploadConfig) -> TusState {
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        TusState::new(db, Arc::new(LocalBlobStore::new(&config.dir)))
+    }
+
+    async fn create_upload(config: &UploadConfig, id: &str, expires_at: DateTime<Utc>) {
+        let info = UploadInfo {
+            user_id: 1,
+            length: 10,
+            filename: None,
+            created_at: expires_at - expiration(config),
+            expires_at,
+            file_id: None,
+        };
+        fs::create_dir_all(tus_dir(config)).await.unwrap();
+        write_info(config, id, &info).await.unwrap();
+        fs::write(data_path(config, id), b"12345").await.unwrap();
+    }
+
+    fn exists(config: &UploadConfig, id: &str) -> bool {
+        info_path(config, id).exists() || data_path(config, id).exists()
+    }
+
+    #[test]
+    fn upload_length_is_required_and_limited() {
+        let config = upload_config(Path::new("uploads"));
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            upload_length(&config, &headers),
+            Err(TusError::BadRequest(_))
+        ));
+
+        headers.insert(UPLOAD_LENGTH, header("0"));
+        assert!(matches!(
+            upload_length(&config, &headers),
+            Err(TusError::BadRequest(_))
+        ));
+
+        headers.insert(UPLOAD_LENGTH, header("1000"));
+        assert_eq!(upload_length(&config, &headers).unwrap(), 1000);
+
+        headers.insert(UPLOAD_LENGTH, header("1001"));
+        assert!(matches!(
+            upload_length(&config, &headers),
+            Err(TusError::TooLarge { limit: 1000 })
+        ));
+
+        headers.insert(UPLOAD_LENGTH, header("-1"));
+        assert!(matches!(
+            upload_length(&config, &headers),
+            Err(TusError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn purges_expired_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = upload_config(dir.path());
+        let state = tus_state(&config);
+        let now = Utc::now();
+        create_upload(&config, "expired", now - Duration::seconds(1)).await;
+        create_upload(&config, "active", now + Duration::seconds(30)).await;
+
+        assert_eq!(state.purge(&config, now).await.unwrap(), 1);
+        assert!(!exists(&config, "expired"));
+        assert!(exists(&config, "active"));
+    }
+
+    #[tokio::test]
+    async fn skips_uploads_being_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = upload_config(dir.path());
+        let state = tus_state(&config);
+        let now = Utc::now();
+        create_upload(&config, "expired", now - Duration::seconds(1)).await;
+
+        let lock = state.lock("expired").unwrap();
+        assert_eq!(state.purge(&config, now).await.unwrap(), 0);
+        assert!(exists(&config, "expired"));
+
+        drop(lock);
+        assert_eq!(state.purge(&config, now).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn purges_corrupted_uploads_by_modification_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = upload_config(dir.path());
+        let state = tus_state(&config);
+        create_upload(&config, "corrupted", Utc::now()).await;
+        fs::write(info_path(&config, "corrupted"), b"{\"user_id\":")
+            .await
+            .unwrap();
+
+        // 刚修改过，还没有过期
+        assert_eq!(state.purge(&config, Utc::now()).await.unwrap(), 0);
+        assert!(exists(&config, "corrupted"));
+
+        let later = Utc::now() + expiration(&config) + Duration::seconds(1);
+        assert_eq!(state.purge(&config, later).await.unwrap(), 1);
+        assert!(!exists(&config, "corrupted"));
+    }
+
+    #[tokio::test]
+    async fn purge_without_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = upload_config(&dir.path().join("missing"));
+        let state = tus_state(&config);
+        assert_eq!(state.purge(&config, Utc::now()).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn metadata_is_optional() {
+        assert!(parse_metadata(None).unwrap().is_empty());
+        assert!(parse_metadata(Some(&header(""))).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decodes_metadata_values() {
+        // filename 报告.pdf，filetype application/pdf
+        let value =
+            header("filename 5oql5ZGKLnBkZg==, filetype YXBwbGljYXRpb24vcGRm,is_confidential");
+        let metadata = parse_metadata(Some(&value)).unwrap();
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata["filename"], "报告.pdf");
+        assert_eq!(metadata["filetype"], "application/pdf");
+        assert_eq!(metadata["is_confidential"], "");
+    }
+
+    #[test]
+    fn rejects_invalid_metadata() {
+        for value in ["filename not-base64!", "filename //79"] {
+            let err = parse_metadata(Some(&header(value))).unwrap_err();
+            assert!(matches!(err, TusError::BadRequest(_)), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn checksum_is_optional() {
+        assert!(parse_checksum(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_supported_checksums() {
+        // "hello" 的 SHA-1 和 SHA-256
+        let value = header("sha1 qvTGHdzF6KLavt4PO0gs2a6pQ00=");
+        let (algorithm, digest) = parse_checksum(Some(&value)).unwrap().unwrap();
+        assert!(matches!(algorithm, ChecksumAlgorithm::Sha1));
+        assert_eq!(digest, Sha1::digest(b"hello").to_vec());
+
+        let value = header("sha256 LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=");
+        let (algorithm, digest) = parse_checksum(Some(&value)).unwrap().unwrap();
+        assert!(matches!(algorithm, ChecksumAlgorithm::Sha256));
+        assert_eq!(digest, Sha256::digest(b"hello").to_vec());
+    }
+
+    #[test]
+    fn rejects_invalid_checksums() {
+        for value in ["sha256", "md5 XUFAKrxLKna5cZ2REBfFkg==", "sha1 not-base64!"] {
+            let err = parse_checksum(Some(&header(value))).unwrap_err();
+            assert!(matches!(err, TusError::BadRequest(_)), "{:?}", value);
+        }
+    }
+}
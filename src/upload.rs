@@ -0,0 +1,773 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use axum::{
+    body::Body,
+    extract::{
+        multipart::{Field, MultipartError},
+        Multipart,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::blob_store::{BlobError, BlobStore};
+use crate::config::UploadConfig;
+
+/// 保存目录下存放未完成上传的子目录；使用本地存储时与保存目录在同一个文件系统中，保证重命名是原子的
+const TEMP_DIR: &str = ".tmp";
+/// 识别文件类型时读取的字节数
+pub const SNIFF_LENGTH: usize = 8192;
+/// 文件名的最大字节数
+const MAX_FILENAME_LENGTH: usize = 255;
+/// 文件名清理后为空时使用的名字
+const DEFAULT_FILENAME: &str = "file";
+
+/// 已保存的文件
+///
+/// 文件以 `key` 为名保存到存储后端，这些信息同时保存为 `{key}.json`。
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StoredFile {
+    /// 存储的 key
+    pub key: String,
+    /// 客户端提交的文件名，已清理
+    pub filename: String,
+    /// 按文件内容识别出的类型
+    pub content_type: String,
+    /// 字节数
+    pub size: u64,
+    /// 内容的 SHA-256，十六进制
+    pub sha256: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// 上传错误
+#[derive(Debug)]
+pub enum UploadError {
+    /// 单个文件超过大小限制
+    TooLarge { filename: String, limit: u64 },
+    /// 文件类型不允许上传
+    UnsupportedType { filename: String, detected: String },
+    /// 请求中没有文件
+    NoFile,
+    /// 解析 multipart 失败，包括请求体超过限制
+    Multipart(MultipartError),
+    /// 读写文件失败
+    Io(std::io::Error),
+    /// 保存到存储后端失败
+    Storage(BlobError),
+    /// 登记到文件目录失败
+    Database(sqlx::Error),
+}
+
+impl Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::TooLarge { filename, limit } => {
+                write!(
+                    f,
+                    "file `{}` exceeds the limit of {} bytes",
+                    filename, limit
+                )
+            }
+            UploadError::UnsupportedType { filename, detected } => {
+                write!(f, "file `{}` has unsupported type `{}`", filename, detected)
+            }
+            UploadError::NoFile => write!(f, "no file in request"),
+            UploadError::Multipart(err) if err.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                write!(f, "request body is too large")
+            }
+            UploadError::Multipart(err) => write!(f, "{}", err.body_text()),
+            UploadError::Io(err) => write!(f, "io error: {}", err),
+            UploadError::Storage(err) => write!(f, "{}", err),
+            UploadError::Database(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+impl From<MultipartError> for UploadError {
+    fn from(err: MultipartError) -> Self {
+        UploadError::Multipart(err)
+    }
+}
+
+impl From<BlobError> for UploadError {
+    fn from(err: BlobError) -> Self {
+        UploadError::Storage(err)
+    }
+}
+
+impl From<sqlx::Error> for UploadError {
+    fn from(err: sqlx::Error) -> Self {
+        UploadError::Database(err)
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(err: std::io::Error) -> Self {
+        UploadError::Io(err)
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        let (status, code) = match &self {
+            UploadError::TooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "file_too_large"),
+            UploadError::UnsupportedType { .. } => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_type")
+            }
+            UploadError::NoFile => (StatusCode::BAD_REQUEST, "no_file"),
+            UploadError::Multipart(err) if err.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "request_too_large")
+            }
+            UploadError::Multipart(err) => (err.status(), "bad_multipart"),
+            UploadError::Io(_) | UploadError::Storage(_) | UploadError::Database(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+            }
+        };
+        let message = if status.is_server_error() {
+            tracing::error!("{}", self);
+            "Internal Server Error".to_string()
+        } else {
+            self.to_string()
+        };
+        let body = Json(json!({
+            "status": if status.is_server_error() { "error" } else { "fail" },
+            "code": code,
+            "message": message,
+        }));
+        (status, body).into_response()
+    }
+}
+
+/// 创建保存目录和临时目录，并清理上次异常退出留下的临时文件
+pub async fn prepare_dir(config: &UploadConfig) -> std::io::Result<()> {
+    let temp_dir = Path::new(&config.dir).join(TEMP_DIR);
+    if fs::try_exists(&temp_dir).await? {
+        fs::remove_dir_all(&temp_dir).await?;
+    }
+    fs::create_dir_all(&temp_dir).await
+}
+
+/// 已接收、尚未保存到存储后端的文件，离开作用域时删除临时文件
+pub struct StagedFile {
+    temp: TempFile,
+    pub file: StoredFile,
+}
+
+impl StagedFile {
+    /// 由已经写好的临时文件创建，只用于测试
+    #[cfg(test)]
+    pub(crate) fn new(path: PathBuf, file: StoredFile) -> Self {
+        Self {
+            temp: TempFile(path),
+            file,
+        }
+    }
+
+    /// 临时文件的路径
+    pub fn path(&self) -> &Path {
+        &self.temp.0
+    }
+}
+
+/// 接收请求中的所有文件
+///
+/// 每个文件流式写入本地临时文件，同时识别类型、计算大小和哈希。任何一个文件失败（包括客户端中途断开）时，
+/// 本次请求的临时文件都会被删除。
+pub async fn receive_all(
+    config: &UploadConfig,
+    mut multipart: Multipart,
+) -> Result<Vec<StagedFile>, UploadError> {
+    let temp_dir = Path::new(&config.dir).join(TEMP_DIR);
+    let mut uploads = vec![];
+    while let Some(field) = multipart.next_field().await? {
+        // 忽略普通的表单字段和没有选择文件的文件字段
+        let Some(filename) = field.file_name().filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        let filename = sanitize_filename(filename);
+        let key = Uuid::new_v4().simple().to_string();
+
+        let temp = TempFile(temp_dir.join(format!("{}.part", key)));
+        let (content_type, size, sha256) = receive(config, field, &filename, &temp.0).await?;
+        let file = StoredFile {
+            key,
+            filename,
+            content_type,
+            size,
+            sha256,
+            uploaded_at: Utc::now(),
+        };
+        uploads.push(StagedFile { temp, file });
+    }
+    if uploads.is_empty() {
+        return Err(UploadError::NoFile);
+    }
+    Ok(uploads)
+}
+
+/// 下载以前按 `{时间戳}_{文件名}` 保存、没有登记到文件目录的文件，类型按扩展名推断
+///
+/// 新上传的文件只能通过文件目录按所有者下载，这里不提供。
+pub async fn download_legacy(store: &dyn BlobStore, id: &str) -> Result<Response, BlobError> {
+    let filename = legacy_filename(id).ok_or_else(|| BlobError::NotFound(id.to_string()))?;
+    let content_type = mime_guess::from_path(filename).first_or_octet_stream();
+    send(store, id, filename, content_type.as_ref()).await
+}
+
+/// 以附件形式返回存储的对象，文件名和类型由调用方提供
+pub async fn send(
+    store: &dyn BlobStore,
+    key: &str,
+    filename: &str,
+    content_type: &str,
+) -> Result<Response, BlobError> {
+    let blob = store.get(key).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_LENGTH, blob.size.to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition(filename)),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        Body::from_stream(blob.stream),
+    )
+        .into_response())
+}
+
+/// 生成 `Content-Disposition: attachment`
+///
+/// `filename` 是只含 ASCII 的备用名，`filename*` 是 RFC 5987 编码的原始文件名。
+pub fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+/// 把本地文件保存到存储后端
+///
+/// 先保存文件，再保存元数据；有元数据的文件才算上传完成。
+pub async fn store_file(
+    store: &dyn BlobStore,
+    path: &Path,
+    file: &StoredFile,
+) -> Result<(), BlobError> {
+    store.put_file(&file.key, path).await?;
+    let meta = serde_json::to_vec(file).map_err(|err| BlobError::Io(std::io::Error::other(err)))?;
+    store.put_bytes(&meta_key(&file.key), meta.into()).await
+}
+
+/// 删除文件和元数据，失败只记录日志
+pub async fn delete(store: &dyn BlobStore, key: &str) {
+    for key in [key.to_string(), meta_key(key)] {
+        if let Err(err) = store.delete(&key).await {
+            tracing::warn!("failed to delete blob {}: {}", key, err);
+        }
+    }
+}
+
+fn meta_key(key: &str) -> String {
+    format!("{}.json", key)
+}
+
+/// 新上传的文件以 32 位十六进制的 uuid 为 key
+pub fn is_key(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// 以前保存的文件名为 `{时间戳}_{文件名}`，返回其中的文件名
+fn legacy_filename(id: &str) -> Option<&str> {
+    let (timestamp, filename) = id.split_once('_')?;
+    let valid = !timestamp.is_empty()
+        && timestamp.bytes().all(|b| b.is_ascii_digit())
+        && !filename.is_empty();
+    valid.then_some(filename)
+}
+
+/// 清理客户端提交的文件名
+///
+/// 只保留最后一个路径分量，去掉控制字符和 Windows 不允许的字符，以及首尾的点和空白；
+/// 超长时截断，清理后为空时使用 `file`。
+pub fn sanitize_filename(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    let mut end = name.len().min(MAX_FILENAME_LENGTH);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    match &name[..end] {
+        "" => DEFAULT_FILENAME.to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// 类型是否在允许列表中，列表项可以是 `image/*`
+pub fn is_allowed_type(allowed: &[String], content_type: &str) -> bool {
+    allowed
+        .iter()
+        .any(|pattern| match pattern.strip_suffix("/*") {
+            Some(kind) => content_type
+                .split_once('/')
+                .is_some_and(|(k, _)| k.eq_ignore_ascii_case(kind)),
+            None => pattern.eq_ignore_ascii_case(content_type),
+        })
+}
+
+// 把字段写入临时文件，返回识别出的类型、大小和 SHA-256
+//
+// 先缓存开头的字节用于识别类型，类型不允许时不再继续读取。
+async fn receive(
+    config: &UploadConfig,
+    mut field: Field<'_>,
+    filename: &str,
+    path: &Path,
+) -> Result<(String, u64, String), UploadError> {
+    let mut file = fs::File::create(path).await?;
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
+    let mut head = vec![];
+    let mut content_type = None;
+    while let Some(chunk) = field.chunk().await? {
+        size += chunk.len() as u64;
+        if size > config.max_upload_size {
+            return Err(UploadError::TooLarge {
+                filename: filename.to_string(),
+                limit: config.max_upload_size,
+            });
+        }
+        hasher.update(&chunk);
+        if content_type.is_some() {
+            file.write_all(&chunk).await?;
+            continue;
+        }
+        head.extend_from_slice(&chunk);
+        if head.len() >= SNIFF_LENGTH {
+            content_type = Some(sniff(config, filename, &head)?);
+            file.write_all(&head).await?;
+        }
+    }
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        // 文件比识别需要的字节数还小
+        None => {
+            let content_type = sniff(config, filename, &head)?;
+            file.write_all(&head).await?;
+            content_type
+        }
+    };
+    file.sync_all().await?;
+    Ok((content_type, size, hex::encode(hasher.finalize())))
+}
+
+/// 按开头的字节识别类型，并检查是否允许上传
+pub fn sniff(config: &UploadConfig, filename: &str, head: &[u8]) -> Result<String, UploadError> {
+    let detected = match infer::get(head) {
+        Some(kind) => kind.mime_type(),
+        None if head.is_empty() => "empty",
+        None => "application/octet-stream",
+    };
+    if !is_allowed_type(&config.allowed_types, detected) {
+        return Err(UploadError::UnsupportedType {
+            filename: filename.to_string(),
+            detected: detected.to_string(),
+        });
+    }
+    Ok(detected.to_string())
+}
+
+/// 临时文件，离开作用域时删除
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // 请求被取消时没有机会执行异步代码，只能同步删除；已经被存储后端移走时会返回 NotFound
+        if let Err(err) = std::fs::remove_file(&self.0) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("failed to remove {}: {}", self.0.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{
+        extract::{FromRequest, Path as AxumPath, State},
+        http::Request,
+        routing::get,
+        Router,
+    };
+    use bytes::Bytes;
+    use futures::{stream, Stream, StreamExt};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::blob_store::LocalBlobStore;
+
+    const BOUNDARY: &str = "X-BOUNDARY";
+
+    // PNG 文件头，补齐到 `len` 字节
+    fn png(len: usize) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        data.resize(len, 0);
+        data
+    }
+
+    fn part(name: &str, filename: Option<&str>, data: &[u8]) -> Vec<u8> {
+        let disposition = match filename {
+            Some(filename) => format!("form-data; name=\"{name}\"; filename=\"{filename}\""),
+            None => format!("form-data; name=\"{name}\""),
+        };
+        let mut part =
+            format!("--{BOUNDARY}\r\nContent-Disposition: {disposition}\r\n\r\n").into_bytes();
+        part.extend_from_slice(data);
+        part.extend_from_slice(b"\r\n");
+        part
+    }
+
+    fn end() -> Vec<u8> {
+        format!("--{BOUNDARY}--\r\n").into_bytes()
+    }
+
+    // 请求体按 `chunks` 分块到达，`Err` 模拟客户端中途断开
+    async fn multipart(chunks: Vec<Result<Vec<u8>, std::io::Error>>) -> Multipart {
+        multipart_stream(stream::iter(chunks)).await
+    }
+
+    async fn multipart_stream(
+        body: impl Stream<Item = Result<Vec<u8>, std::io::Error>> + Send + 'static,
+    ) -> Multipart {
+        let request = Request::post("/upload")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(Body::from_stream(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    async fn receive_err(
+        config: &UploadConfig,
+        chunks: Vec<Result<Vec<u8>, std::io::Error>>,
+    ) -> UploadError {
+        match receive_all(config, multipart(chunks).await).await {
+            Ok(staged) => panic!("expected an error, received {} files", staged.len()),
+            Err(err) => err,
+        }
+    }
+
+    async fn config(dir: &Path, max_upload_size: u64) -> UploadConfig {
+        let config = UploadConfig {
+            dir: dir.to_str().unwrap().to_string(),
+            max_upload_size,
+            ..UploadConfig::default()
+        };
+        prepare_dir(&config).await.unwrap();
+        config
+    }
+
+    fn temp_files(config: &UploadConfig) -> usize {
+        std::fs::read_dir(Path::new(&config.dir).join(TEMP_DIR))
+            .unwrap()
+            .count()
+    }
+
+    #[tokio::test]
+    async fn receives_files_with_size_and_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 1024 * 1024).await;
+        let small = png(100);
+        let large = png(SNIFF_LENGTH * 3 + 7);
+        let mut body = part("title", None, b"holiday");
+        body.extend(part("a", Some("../a.png"), &small));
+        body.extend(part("b", Some("b.png"), &large));
+        body.extend(part("c", Some(""), b""));
+        body.extend(end());
+        // 分成小块，覆盖识别类型前需要拼接多个块的情况
+        let chunks = body.chunks(1000).map(|chunk| Ok(chunk.to_vec())).collect();
+
+        let staged = receive_all(&config, multipart(chunks).await).await.unwrap();
+        assert_eq!(staged.len(), 2);
+        for (staged, data, filename) in
+            [(&staged[0], &small, "a.png"), (&staged[1], &large, "b.png")]
+        {
+            assert_eq!(staged.file.filename, filename);
+            assert_eq!(staged.file.content_type, "image/png");
+            assert_eq!(staged.file.size, data.len() as u64);
+            assert_eq!(staged.file.sha256, hex::encode(Sha256::digest(data)));
+            assert_eq!(&std::fs::read(staged.path()).unwrap(), data);
+        }
+
+        // 离开作用域时删除临时文件
+        drop(staged);
+        assert_eq!(temp_files(&config), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_files_over_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 1000).await;
+        let mut body = part("a", Some("a.png"), &png(1001));
+        body.extend(end());
+
+        let err = receive_err(&config, vec![Ok(body)]).await;
+        assert!(
+            matches!(err, UploadError::TooLarge { ref filename, limit: 1000 } if filename == "a.png"),
+            "{err:?}"
+        );
+        assert_eq!(temp_files(&config), 0);
+
+        // 正好等于限制的可以上传
+        let mut body = part("a", Some("a.png"), &png(1000));
+        body.extend(end());
+        let staged = receive_all(&config, multipart(vec![Ok(body)]).await)
+            .await
+            .unwrap();
+        assert_eq!(staged[0].file.size, 1000);
+    }
+
+    #[tokio::test]
+    async fn rejects_type_after_sniffing_the_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 1024 * 1024).await;
+        // 只发送开头的字节，之后的内容永远不会到达；类型不允许时不应再等待后面的内容
+        let head = part("a", Some("a.bin"), &[0; SNIFF_LENGTH + 1024]);
+        let head = head[..head.len() - 2].to_vec();
+        let body = stream::iter([Ok(head)]).chain(stream::pending());
+
+        let received = receive_all(&config, multipart_stream(body).await);
+        let err = match tokio::time::timeout(Duration::from_secs(5), received).await {
+            Ok(Err(err)) => err,
+            Ok(Ok(_)) => panic!("expected an error"),
+            Err(_) => panic!("kept reading after the head was sniffed"),
+        };
+        assert!(
+            matches!(err, UploadError::UnsupportedType { ref detected, .. } if detected == "application/octet-stream"),
+            "{err:?}"
+        );
+        assert_eq!(temp_files(&config), 0);
+    }
+
+    #[tokio::test]
+    async fn failure_removes_earlier_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 1024 * 1024).await;
+        let mut body = part("a", Some("a.png"), &png(100));
+        body.extend(part("b", Some("b.bin"), &[0; 100]));
+        body.extend(end());
+
+        let err = receive_err(&config, vec![Ok(body)]).await;
+        assert!(
+            matches!(err, UploadError::UnsupportedType { .. }),
+            "{err:?}"
+        );
+        assert_eq!(temp_files(&config), 0);
+    }
+
+    #[tokio::test]
+    async fn disconnect_removes_partial_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 1024 * 1024).await;
+        let mut body = part("a", Some("a.png"), &png(100));
+        body.extend(part("b", Some("b.png"), &png(SNIFF_LENGTH * 2)));
+        body.truncate(body.len() - 100);
+        let chunks = vec![
+            Ok(body),
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)),
+        ];
+
+        let err = receive_err(&config, chunks).await;
+        assert!(matches!(err, UploadError::Multipart(_)), "{err:?}");
+        assert_eq!(temp_files(&config), 0);
+    }
+
+    #[tokio::test]
+    async fn requires_at_least_one_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 1024 * 1024).await;
+        let mut body = part("title", None, b"holiday");
+        body.extend(end());
+
+        let err = receive_err(&config, vec![Ok(body)]).await;
+        assert!(matches!(err, UploadError::NoFile), "{err:?}");
+    }
+
+    #[test]
+    fn keeps_plain_filenames() {
+        assert_eq!(sanitize_filename("report.pdf"), "report.pdf");
+        assert_eq!(sanitize_filename("报告 2024.docx"), "报告 2024.docx");
+    }
+
+    #[test]
+    fn strips_directories() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename(r"C:\Users\alice\photo.png"), "photo.png");
+        assert_eq!(sanitize_filename("dir/"), DEFAULT_FILENAME);
+    }
+
+    #[test]
+    fn removes_unsafe_characters() {
+        assert_eq!(sanitize_filename("a<b>c:d\"e|f?g*.txt"), "abcdefg.txt");
+        assert_eq!(sanitize_filename("line\r\nbreak\0.txt"), "linebreak.txt");
+        assert_eq!(sanitize_filename("  ..hidden.txt. "), "hidden.txt");
+    }
+
+    #[test]
+    fn parses_legacy_names() {
+        assert_eq!(legacy_filename("1715159123_测试.pdf"), Some("测试.pdf"));
+        assert_eq!(legacy_filename("1715157069_dog_2.jpg"), Some("dog_2.jpg"));
+        assert_eq!(legacy_filename("0123456789abcdef0123456789abcdef"), None);
+        assert_eq!(legacy_filename("abc_dog.jpg"), None);
+        assert_eq!(legacy_filename("_dog.jpg"), None);
+        assert_eq!(legacy_filename("1715157069_"), None);
+    }
+
+    #[tokio::test]
+    async fn downloads_legacy_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalBlobStore::new(dir.path());
+        store
+            .put_bytes("1715159123_测试.pdf", Bytes::from_static(b"%PDF-1.4"))
+            .await
+            .unwrap();
+
+        let response = download_legacy(&store, "1715159123_测试.pdf")
+            .await
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(headers[header::CONTENT_TYPE], "application/pdf");
+        assert_eq!(headers[header::CONTENT_LENGTH], "8");
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"__.pdf\"; filename*=UTF-8''%E6%B5%8B%E8%AF%95.pdf"
+        );
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"%PDF-1.4");
+    }
+
+    #[tokio::test]
+    async fn legacy_download_rejects_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("uploads");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(dir.path().join("passwd"), b"secret").unwrap();
+        let store: Arc<dyn BlobStore> = Arc::new(LocalBlobStore::new(&root));
+
+        let app =
+            Router::new()
+                .route(
+                    "/files/:id",
+                    get(
+                        |State(store): State<Arc<dyn BlobStore>>,
+                         AxumPath(id): AxumPath<String>| async move {
+                            download_legacy(store.as_ref(), &id).await
+                        },
+                    ),
+                )
+                .with_state(store);
+        // 路径参数解码后是 `1_../passwd`
+        for uri in [
+            "/files/1_..%2Fpasswd",
+            "/files/1_..%5Cpasswd",
+            "/files/1_%00",
+        ] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+    }
+
+    #[test]
+    fn falls_back_when_nothing_is_left() {
+        for name in ["", "..", " . ", "***", "/"] {
+            assert_eq!(sanitize_filename(name), DEFAULT_FILENAME, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        let name = sanitize_filename(&"a".repeat(300));
+        assert_eq!(name.len(), MAX_FILENAME_LENGTH);
+
+        // 汉字占 3 字节，第 255 字节落在字符中间，退回到前一个字符边界
+        let name = sanitize_filename(&format!("a{}", "文".repeat(100)));
+        assert_eq!(name.len(), 1 + 84 * 3);
+    }
+
+    #[test]
+    fn content_disposition_for_ascii_name() {
+        assert_eq!(
+            content_disposition("report.pdf"),
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+    }
+
+    #[test]
+    fn content_disposition_encodes_non_ascii() {
+        assert_eq!(
+            content_disposition("报告 1.pdf"),
+            "attachment; filename=\"__ 1.pdf\"; filename*=UTF-8''%E6%8A%A5%E5%91%8A%201.pdf"
+        );
+    }
+
+    #[test]
+    fn content_disposition_escapes_quotes() {
+        assert_eq!(
+            content_disposition("a\"b\\c;d.txt"),
+            "attachment; filename=\"a_b_c;d.txt\"; filename*=UTF-8''a%22b%5Cc%3Bd.txt"
+        );
+    }
+}
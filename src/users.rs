@@ -0,0 +1,479 @@
+use std::sync::OnceLock;
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{FromRow, Pool, Postgres};
+
+use crate::config::AppConfig;
+use crate::error::AppError;
+
+/// 密码最短长度
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// 密码最长长度，避免超长密码消耗过多 CPU
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// 用户不存在时用于校验的哈希，让“用户不存在”和“密码错误”耗时相同
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+/// 用户
+#[derive(Serialize, Debug, FromRow)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    /// 角色
+    pub roles: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 注册
+#[derive(Deserialize)]
+pub struct Register {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// 登录
+#[derive(Deserialize)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+}
+
+/// 修改密码
+#[derive(Deserialize)]
+pub struct ChangePassword {
+    pub username: String,
+    pub old_password: String,
+    pub new_password: String,
+}
+
+/// 用户相关的路由：`/register`、`/login`、`/password`
+pub fn router() -> Router<Pool<Postgres>> {
+    Router::new()
+        .route("/register", post(register_handler))
+        .route("/login", post(login_handler))
+        .route("/password", post(change_password_handler))
+}
+
+/// 注册用户，用户名或邮箱重复时返回 409
+pub async fn register(db: &Pool<Postgres>, body: Register) -> Result<User, AppError> {
+    validate_username(&body.username)?;
+    if body.email.len() > 255 || !body.email.contains('@') {
+        return Err(AppError::BadRequest("邮箱格式不正确".to_string()));
+    }
+    validate_password(&body.password)?;
+
+    let password_hash = hash_password(body.password).await?;
+    let user = sqlx::query_as::<_, User>(
+        "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, $3) \
+         RETURNING id, username, email, password_hash, roles, created_at",
+    )
+    .bind(&body.username)
+    .bind(&body.email)
+    .bind(&password_hash)
+    .fetch_one(db)
+    .await?;
+    Ok(user)
+}
+
+/// 校验用户名和密码，不匹配时返回 `None`
+///
+/// 用户不存在时同样会计算一次哈希，避免通过响应时间判断用户是否存在。
+/// 哈希的成本参数与当前配置不同时，会用当前参数重新计算并保存。
+pub async fn authenticate(
+    db: &Pool<Postgres>,
+    username: &str,
+    password: &str,
+) -> Result<Option<User>, AppError> {
+    let user = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, roles, created_at FROM users WHERE username = $1",
+    )
+    .bind(username)
+    .fetch_optional(db)
+    .await?;
+
+    let Some(mut user) = user else {
+        verify_password(password.to_string(), None).await?;
+        return Ok(None);
+    };
+
+    let verified = verify_password(password.to_string(), Some(user.password_hash.clone())).await?;
+    match verified {
+        Verified::No => Ok(None),
+        Verified::Yes => Ok(Some(user)),
+        Verified::NeedsRehash => {
+            let new_hash = hash_password(password.to_string()).await?;
+            // 只有哈希没有被并发修改时才更新
+            let result = sqlx::query(
+                "UPDATE users SET password_hash = $1, updated_at = now() \
+                 WHERE id = $2 AND password_hash = $3",
+            )
+            .bind(&new_hash)
+            .bind(user.id)
+            .bind(&user.password_hash)
+            .execute(db)
+            .await;
+            match result {
+                Ok(_) => user.password_hash = new_hash,
+                Err(err) => tracing::error!("failed to rehash password of {}: {}", user.id, err),
+            }
+            Ok(Some(user))
+        }
+    }
+}
+
+/// 按邮箱查找用户
+pub async fn find_by_email(db: &Pool<Postgres>, email: &str) -> Result<Option<User>, AppError> {
+    let user = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, roles, created_at FROM users WHERE email = $1",
+    )
+    .bind(email)
+    .fetch_optional(db)
+    .await?;
+    Ok(user)
+}
+
+/// 按身份的 subject 查找用户；Session 的 subject 是用户名，访问令牌的是邮箱
+pub async fn find_by_subject(db: &Pool<Postgres>, subject: &str) -> Result<Option<User>, AppError> {
+    let user = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, roles, created_at FROM users \
+         WHERE username = $1 OR email = $1",
+    )
+    .bind(subject)
+    .fetch_optional(db)
+    .await?;
+    Ok(user)
+}
+
+/// 修改密码，需要提供旧密码
+pub async fn change_password(db: &Pool<Postgres>, body: ChangePassword) -> Result<(), AppError> {
+    validate_password(&body.new_password)?;
+    let user = authenticate(db, &body.username, &body.old_password)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("用户名或密码错误".to_string()))?;
+
+    let password_hash = hash_password(body.new_password).await?;
+    sqlx::query("UPDATE users SET password_hash = $1, updated_at = now() WHERE id = $2")
+        .bind(&password_hash)
+        .bind(user.id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn register_handler(
+    State(db): State<Pool<Postgres>>,
+    Json(body): Json<Register>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = register(&db, body).await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LOCATION,
+        format!("/users/{}", user.id).parse().unwrap(),
+    );
+    Ok((
+        StatusCode::CREATED,
+        headers,
+        Json(json!({"status": "success", "data": user})),
+    ))
+}
+
+async fn login_handler(
+    State(db): State<Pool<Postgres>>,
+    Json(body): Json<Login>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = authenticate(&db, &body.username, &body.password)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("用户名或密码错误".to_string()))?;
+    Ok(Json(json!({"status": "success", "data": user})))
+}
+
+async fn change_password_handler(
+    State(db): State<Pool<Postgres>>,
+    Json(body): Json<ChangePassword>,
+) -> Result<impl IntoResponse, AppError> {
+    change_password(&db, body).await?;
+    Ok(Json(json!({"status": "success", "message": "密码已修改"})))
+}
+
+fn validate_username(username: &str) -> Result<(), AppError> {
+    let valid = (3..=64).contains(&username.chars().count())
+        && username
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if !valid {
+        return Err(AppError::BadRequest(
+            "用户名为 3-64 个字母、数字、`_`、`-` 或 `.`".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_password(password: &str) -> Result<(), AppError> {
+    let len = password.chars().count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&len) {
+        return Err(AppError::BadRequest(format!(
+            "密码长度必须为 {}-{} 个字符",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+// 按当前配置的参数创建 Argon2id 哈希器
+fn hasher() -> Argon2<'static> {
+    let config = &AppConfig::global().password;
+    // 参数在加载配置时已经校验过
+    let params = Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )
+    .expect("invalid password hashing params");
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+// 计算密码哈希，结果为 PHC 字符串
+async fn hash_password(password: String) -> Result<String, AppError> {
+    // 哈希很耗 CPU，放到阻塞线程池中执行
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        hasher()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|err| AppError::Internal(err.to_string()))?
+    .map_err(|err| AppError::Internal(err.to_string()))
+}
+
+enum Verified {
+    No,
+    Yes,
+    /// 密码正确，但哈希的算法或参数已经过时
+    NeedsRehash,
+}
+
+// 校验密码；`hash` 为 `None` 时与假哈希比较，结果总是不匹配
+async fn verify_password(password: String, hash: Option<String>) -> Result<Verified, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let is_dummy = hash.is_none();
+        let hash = hash.unwrap_or_else(|| dummy_hash().to_string());
+        let parsed = PasswordHash::new(&hash).map_err(|err| AppError::Internal(err.to_string()))?;
+        // 使用哈希中记录的算法和参数校验，比较是常数时间的
+        let matched = Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok();
+        Ok(match (matched && !is_dummy, needs_rehash(&parsed)) {
+            (false, _) => Verified::No,
+            (true, false) => Verified::Yes,
+            (true, true) => Verified::NeedsRehash,
+        })
+    })
+    .await
+    .map_err(|err| AppError::Internal(err.to_string()))?
+}
+
+// 哈希的算法、版本或成本参数与当前配置不同
+fn needs_rehash(hash: &PasswordHash) -> bool {
+    let current = hasher();
+    let current = current.params();
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    match Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+// 使用当前参数计算的假哈希，只计算一次
+fn dummy_hash() -> &'static str {
+    DUMMY_HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        hasher()
+            .hash_password(b"axum.rs dummy password", &salt)
+            .expect("failed to compute dummy password hash")
+            .to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 用较低的成本参数计算哈希，模拟配置修改之前保存的哈希
+    fn legacy_hash(password: &str) -> String {
+        let params = Params::new(8, 1, 1, None).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(password.as_bytes(), &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    async fn insert_user(db: &Pool<Postgres>, username: &str, password_hash: &str) {
+        sqlx::query("INSERT INTO users (username, email, password_hash) VALUES ($1, $2, $3)")
+            .bind(username)
+            .bind(format!("{username}@example.com"))
+            .bind(password_hash)
+            .execute(db)
+            .await
+            .unwrap();
+    }
+
+    async fn stored_hash(db: &Pool<Postgres>, username: &str) -> String {
+        sqlx::query_scalar("SELECT password_hash FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn current_params_do_not_need_rehash() {
+        let hash = hash_with(&hasher(), "password123");
+        assert!(!needs_rehash(&PasswordHash::new(&hash).unwrap()));
+    }
+
+    #[test]
+    fn old_params_need_rehash() {
+        let hash = legacy_hash("password123");
+        assert!(needs_rehash(&PasswordHash::new(&hash).unwrap()));
+    }
+
+    #[test]
+    fn other_algorithm_needs_rehash() {
+        let current = hasher();
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, current.params().clone());
+        let hash = hash_with(&argon2i, "password123");
+        assert!(needs_rehash(&PasswordHash::new(&hash).unwrap()));
+    }
+
+    fn hash_with(argon2: &Argon2, password: &str) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        argon2
+            .hash_password(password.as_bytes(), &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn verify_reports_outdated_hash() {
+        let hash = legacy_hash("password123");
+        let verified = verify_password("password123".to_string(), Some(hash.clone()))
+            .await
+            .unwrap();
+        assert!(matches!(verified, Verified::NeedsRehash));
+
+        let verified = verify_password("wrong-password".to_string(), Some(hash))
+            .await
+            .unwrap();
+        assert!(matches!(verified, Verified::No));
+    }
+
+    #[tokio::test]
+    async fn dummy_hash_never_matches() {
+        let verified = verify_password("axum.rs dummy password".to_string(), None)
+            .await
+            .unwrap();
+        assert!(matches!(verified, Verified::No));
+    }
+
+    #[sqlx::test]
+    async fn login_rehashes_outdated_hash(db: Pool<Postgres>) {
+        let old_hash = legacy_hash("password123");
+        insert_user(&db, "alice", &old_hash).await;
+
+        let user = authenticate(&db, "alice", "password123")
+            .await
+            .unwrap()
+            .unwrap();
+        let new_hash = stored_hash(&db, "alice").await;
+        assert_ne!(new_hash, old_hash);
+        assert_eq!(user.password_hash, new_hash);
+        assert!(!needs_rehash(&PasswordHash::new(&new_hash).unwrap()));
+
+        // 新哈希仍然可以登录，且不再重新计算
+        authenticate(&db, "alice", "password123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored_hash(&db, "alice").await, new_hash);
+    }
+
+    #[sqlx::test]
+    async fn wrong_password_keeps_outdated_hash(db: Pool<Postgres>) {
+        let old_hash = legacy_hash("password123");
+        insert_user(&db, "alice", &old_hash).await;
+
+        assert!(authenticate(&db, "alice", "wrong-password")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(stored_hash(&db, "alice").await, old_hash);
+    }
+
+    #[sqlx::test]
+    async fn unknown_user_still_verifies(db: Pool<Postgres>) {
+        assert!(authenticate(&db, "nobody", "password123")
+            .await
+            .unwrap()
+            .is_none());
+        // 与假哈希比较过一次
+        assert!(DUMMY_HASH.get().is_some());
+    }
+
+    #[test]
+    fn username_length_boundaries() {
+        assert!(validate_username("ab").is_err());
+        assert!(validate_username("abc").is_ok());
+        assert!(validate_username(&"a".repeat(64)).is_ok());
+        assert!(validate_username(&"a".repeat(65)).is_err());
+        // 按字符计数，不按字节
+        assert!(validate_username("张三丰").is_ok());
+        assert!(validate_username(&"张".repeat(64)).is_ok());
+    }
+
+    #[test]
+    fn username_character_set() {
+        assert!(validate_username("a_b-c.d9").is_ok());
+        for username in ["a b", "a@b", "a/b", "a\\b", "a\0b", "a+b"] {
+            assert!(validate_username(username).is_err(), "{username:?}");
+        }
+    }
+
+    #[test]
+    fn password_length_boundaries() {
+        assert!(validate_password(&"a".repeat(MIN_PASSWORD_LENGTH - 1)).is_err());
+        assert!(validate_password(&"a".repeat(MIN_PASSWORD_LENGTH)).is_ok());
+        assert!(validate_password(&"a".repeat(MAX_PASSWORD_LENGTH)).is_ok());
+        assert!(validate_password(&"a".repeat(MAX_PASSWORD_LENGTH + 1)).is_err());
+        // 按字符计数，不按字节
+        assert!(validate_password(&"密".repeat(MIN_PASSWORD_LENGTH)).is_ok());
+        assert!(validate_password(&"密".repeat(MAX_PASSWORD_LENGTH + 1)).is_err());
+    }
+}